@@ -8,18 +8,35 @@ mod api;
 mod utils;
 
 use utils::settings::cloudprovider::update_provider;
+use utils::settings::credentials::{create_credential, delete_credential, list_credentials, migrate_cloud_credentials, update_credential};
+use utils::settings::keyring::Keyring;
 use utils::deployment::deploy;
 use utils::database::db;
 use utils::settings::app_config;
-use utils::database::db::{create_indexes};
+use utils::database::db::{create_indexes, fail_interrupted_jobs, release_interrupted_template_claims, fail_interrupted_deployments, migrate_legacy_deployment_status};
 use utils::deployment::deploy::{deploy, undeploy};
+use utils::deployment::cloud_init::migrate_deployment_user_data;
 use utils::deployment::deployments::fetch_deployment_by_user_email;
 use utils::user::signup_func::{handle_signup, init_mongo_client};
 use utils::user::login_func::handle_login;
 use utils::s3_bucket_handler::s3_handler;
 use utils::terraform::terraform_handler;
 use utils::user::check_auth;
+use utils::jobs::job_queue::JobQueue;
+use utils::jobs::job_status::get_job_status;
+use utils::terraform::log_stream::LogHub;
+use utils::storage::artifact_store::{build_store, ArtifactStore};
+use utils::deployment::deployment_logs::stream_deployment_logs;
+use utils::deployment::deployment_runs::{get_deployment_run_log, list_deployment_runs};
+use utils::deployment::plan::plan_deployment;
+use utils::templates::catalog::list_templates;
+use utils::apps::catalog::list_apps;
+use utils::providers::cloud_provider::list_providers;
+use utils::templates::versions::{get_template_versions, publish_initial_versions, publish_template};
+use utils::deployment::upgrade::{apply_upgrade, plan_upgrade};
+use utils::deployment::firewall::{get_firewall_rules, update_firewall_rules};
 use app_config::AppConfig;
+use std::sync::Arc;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
@@ -51,14 +68,43 @@ async fn main() -> std::io::Result<()> {
         }
     };
 
+    // Without the keys no stored credential can be opened, so there is nothing useful to start
+    let keyring = match Keyring::from_config(&app_config) {
+        Ok(keyring) => Arc::new(keyring),
+        Err(e) => {
+            eprintln!("❌ Failed to load credential keys: {}", e);
+            std::process::exit(1);
+        }
+    };
+    println!("🔐 Sealing credentials with key '{}'", keyring.active_key_id());
+    println!("----------------------------------------");
+
+    let store = build_store(&app_config).await;
+    println!("🗄️ Using artifact store: {}", store.describe());
+    println!("----------------------------------------");
+
+    fail_interrupted_jobs(&mongo_client).await;
+    migrate_legacy_deployment_status(&mongo_client).await;
+    migrate_cloud_credentials(&mongo_client, &keyring).await;
+    migrate_deployment_user_data(&mongo_client, store.as_ref(), &keyring).await;
+    fail_interrupted_deployments(&mongo_client).await;
+    release_interrupted_template_claims(&mongo_client).await;
+    publish_initial_versions(&mongo_client, store.as_ref()).await;
+    let log_hub = LogHub::default();
+    let job_queue = JobQueue::start(app_config.deploy_workers, app_config.clone(), mongo_client.clone(), store.clone(), keyring.clone(), log_hub.clone());
+
     println!("🚀 Starting API server on http://localhost:8080");
     println!("----------------------------------------");
 
-    create_http_server(mongo_client, app_config).await
+    create_http_server(mongo_client, app_config, store, keyring, job_queue, log_hub).await
 }
 
-pub async fn create_http_server(mongo_client: Client, app_config: AppConfig) -> std::io::Result<()> {
+pub async fn create_http_server(mongo_client: Client, app_config: AppConfig, store: Arc<dyn ArtifactStore>, keyring: Arc<Keyring>, job_queue: JobQueue, log_hub: LogHub) -> std::io::Result<()> {
     let app_config_data = web::Data::new(app_config);
+    let store_data: web::Data<dyn ArtifactStore> = web::Data::from(store);
+    let keyring_data = web::Data::from(keyring);
+    let job_queue_data = web::Data::new(job_queue);
+    let log_hub_data = web::Data::new(log_hub);
 
     HttpServer::new(move || {
         let cors = Cors::default()
@@ -76,12 +122,46 @@ pub async fn create_http_server(mongo_client: Client, app_config: AppConfig) ->
             .wrap(cors)
             .app_data(web::Data::new(mongo_client.clone()))
             .app_data(app_config_data.clone())
+            .app_data(store_data.clone())
+            .app_data(keyring_data.clone())
+            .app_data(job_queue_data.clone())
+            .app_data(log_hub_data.clone())
             .service(web::resource("/signup").route(web::post().to(handle_signup)))
             .service(web::resource("/login").route(web::post().to(handle_login)))
             .service(web::resource("/deployments").route(web::get().to(fetch_deployment_by_user_email)))
+            .service(web::resource("/deployments/{project_id}/logs").route(web::get().to(stream_deployment_logs)))
+            .service(web::resource("/deployments/{project_id}/runs").route(web::get().to(list_deployment_runs)))
+            .service(web::resource("/deployments/{project_id}/runs/{run_id}/log").route(web::get().to(get_deployment_run_log)))
+            .service(web::resource("/deployments/{project_id}/upgrade/plan").route(web::post().to(plan_upgrade)))
+            .service(web::resource("/deployments/{project_id}/upgrade").route(web::post().to(apply_upgrade)))
+            .service(
+                web::resource("/deployments/{project_id}/firewall")
+                    .route(web::get().to(get_firewall_rules))
+                    .route(web::post().to(update_firewall_rules)),
+            )
             .service(web::resource("/deploy").route(web::post().to(deploy)))
+            .service(web::resource("/deploy/plan").route(web::post().to(plan_deployment)))
+            .service(web::resource("/templates").route(web::get().to(list_templates)))
+            .service(web::resource("/apps").route(web::get().to(list_apps)))
+            .service(web::resource("/providers").route(web::get().to(list_providers)))
+            .service(
+                web::resource("/templates/{name}/versions")
+                    .route(web::get().to(get_template_versions))
+                    .route(web::post().to(publish_template)),
+            )
+            .service(web::resource("/jobs/{job_id}").route(web::get().to(get_job_status)))
             .service(web::resource("/undeploy").route(web::post().to(undeploy)))
             .service(web::resource("/settings").route(web::post().to(update_provider)))
+            .service(
+                web::resource("/credentials")
+                    .route(web::get().to(list_credentials))
+                    .route(web::post().to(create_credential)),
+            )
+            .service(
+                web::resource("/credentials/{credential_id}")
+                    .route(web::put().to(update_credential))
+                    .route(web::delete().to(delete_credential)),
+            )
             .service(web::resource("/check-auth").route(web::get().to(check_auth::check_auth)))
             .service(web::resource("/").route(web::get().to(|| async {
                 HttpResponse::Ok().body("API is running")
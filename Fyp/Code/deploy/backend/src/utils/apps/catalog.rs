@@ -0,0 +1,211 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::ops::Deref;
+use actix_web::{web, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    deploy::ApiResponse,
+    utils::providers::cloud_provider::{CloudProvider, SERVER_SIZES},
+    utils::storage::artifact_store::ArtifactStore,
+    utils::templates::catalog::is_valid_template_name,
+};
+
+// Lives next to the compose files it describes
+pub const APP_CATALOG_KEY: &str = "compose/catalog.json";
+pub const COMPOSE_PREFIX: &str = "compose/";
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct AppPort {
+    pub port: u16,
+    // "tcp" or "udp"
+    pub protocol: String,
+    #[serde(default)]
+    pub description: String,
+}
+
+// A value the compose file takes as `__NAME__`, supplied per deployment
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct AppParameter {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub required: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<String>,
+    // Passwords and the like; clients should mask the input, and the value only ever reaches the server inside sealed cloud-init
+    #[serde(default)]
+    pub secret: bool,
+}
+
+// Values a request gives for its app's parameters. Some are passwords, so Debug (and with it request logging) shows only the names
+#[derive(Deserialize, Serialize, Default)]
+#[serde(transparent)]
+pub struct AppParameterValues(pub HashMap<String, String>);
+
+impl Deref for AppParameterValues {
+    type Target = HashMap<String, String>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl fmt::Debug for AppParameterValues {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.0.keys()).finish()
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct AppDefinition {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    // File name under `compose/`
+    pub compose_file: String,
+    // Matched against the `app_types` a template manifest supports
+    #[serde(rename = "type", default = "default_app_type")]
+    pub app_type: String,
+    #[serde(default)]
+    pub ports: Vec<AppPort>,
+    // One of the provider-neutral SERVER_SIZES
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_server_size: Option<String>,
+    #[serde(default)]
+    pub parameters: Vec<AppParameter>,
+}
+
+#[derive(Deserialize)]
+struct AppCatalog {
+    apps: Vec<AppDefinition>,
+}
+
+fn default_app_type() -> String {
+    "docker-compose".into()
+}
+
+fn is_valid_parameter_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+}
+
+// Values are pasted into YAML (sometimes unquoted), so anything that could end a scalar or start a comment is refused
+fn is_safe_parameter_value(value: &str) -> bool {
+    value
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "._-@+=/:,$%~^".contains(c))
+}
+
+// A plain file name directly under `compose/`
+fn is_valid_compose_file(file: &str) -> bool {
+    !file.is_empty()
+        && !file.starts_with('.')
+        && file.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_')
+}
+
+fn validate_app(app: &AppDefinition) -> Result<(), String> {
+    if !is_valid_template_name(&app.id) {
+        return Err(format!("invalid app id '{}'", app.id));
+    }
+    if !is_valid_compose_file(&app.compose_file) {
+        return Err(format!("app '{}' has an invalid compose_file '{}'", app.id, app.compose_file));
+    }
+    if let Some(port) = app.ports.iter().find(|port| port.protocol != "tcp" && port.protocol != "udp") {
+        return Err(format!("app '{}' declares port {} with unknown protocol '{}'", app.id, port.port, port.protocol));
+    }
+    if let Some(size) = app.min_server_size.as_deref().filter(|size| !SERVER_SIZES.contains(size)) {
+        return Err(format!("app '{}' has unknown min_server_size '{}'", app.id, size));
+    }
+    if let Some(parameter) = app.parameters.iter().find(|parameter| !is_valid_parameter_name(&parameter.name)) {
+        return Err(format!("app '{}' has invalid parameter name '{}'", app.id, parameter.name));
+    }
+    Ok(())
+}
+
+pub async fn load_app_catalog(store: &dyn ArtifactStore) -> Result<Vec<AppDefinition>, Box<dyn std::error::Error + Send + Sync>> {
+    let Some(bytes) = store.get(APP_CATALOG_KEY).await? else {
+        return Ok(Vec::new());
+    };
+
+    let catalog: AppCatalog = serde_json::from_slice(&bytes).map_err(|e| format!("Invalid {}: {}", APP_CATALOG_KEY, e))?;
+
+    let mut seen = HashSet::new();
+    for app in &catalog.apps {
+        validate_app(app).map_err(|e| format!("Invalid {}: {}", APP_CATALOG_KEY, e))?;
+        if !seen.insert(app.id.as_str()) {
+            return Err(format!("Invalid {}: app '{}' is listed twice", APP_CATALOG_KEY, app.id).into());
+        }
+    }
+
+    let mut apps = catalog.apps;
+    apps.sort_by(|a, b| a.id.cmp(&b.id));
+    Ok(apps)
+}
+
+pub async fn find_app(store: &dyn ArtifactStore, app_id: &str) -> Result<Option<AppDefinition>, Box<dyn std::error::Error + Send + Sync>> {
+    Ok(load_app_catalog(store).await?.into_iter().find(|app| app.id == app_id))
+}
+
+// Checks the request's values against the app's parameters and returns the `__NAME__` replacements for its compose file
+pub fn resolve_app_parameters(app: &AppDefinition, values: &HashMap<String, String>) -> Result<HashMap<String, String>, String> {
+    if let Some(name) = values.keys().find(|name| !app.parameters.iter().any(|parameter| &parameter.name == *name)) {
+        return Err(format!("App '{}' has no parameter '{}'", app.id, name));
+    }
+
+    let mut replacements = HashMap::new();
+    for parameter in &app.parameters {
+        let value = match values.get(&parameter.name).or(parameter.default.as_ref()) {
+            Some(value) => value,
+            None if parameter.required => return Err(format!("Missing value for app parameter '{}'", parameter.name)),
+            None => continue,
+        };
+
+        if !is_safe_parameter_value(value) {
+            return Err(format!("App parameter '{}' contains characters that are not allowed", parameter.name));
+        }
+
+        // `$` starts variable interpolation in compose files, so it is doubled to stay literal
+        replacements.insert(format!("__{}__", parameter.name), value.replace('$', "$$"));
+    }
+
+    Ok(replacements)
+}
+
+// Compares memory, so a provider's own server types count too and not just the size names
+pub fn check_server_size(app: &AppDefinition, provider: &dyn CloudProvider, selected_server: &str) -> Result<(), String> {
+    let Some(min_size) = app.min_server_size.as_deref() else {
+        return Ok(());
+    };
+
+    match (provider.server_type(selected_server), provider.server_type(min_size)) {
+        (Some(selected), Some(minimum)) if selected.memory_mb < minimum.memory_mb => Err(format!(
+            "App '{}' needs at least a {} server ({} on {}), '{}' is too small",
+            app.id, min_size, minimum.name, provider.name(), selected_server
+        )),
+        _ => Ok(()),
+    }
+}
+
+pub async fn list_apps(store: web::Data<dyn ArtifactStore>) -> impl Responder {
+    match load_app_catalog(store.get_ref()).await {
+        Ok(apps) => HttpResponse::Ok().json(ApiResponse {
+            status: "success".into(),
+            message: if apps.is_empty() {
+                "No apps found".into()
+            } else {
+                "Apps fetched successfully".into()
+            },
+            returneddata: Some(json!({ "apps": apps })),
+        }),
+        Err(e) => {
+            eprintln!("❌ Error listing apps: {}", e);
+            HttpResponse::InternalServerError().json(ApiResponse {
+                status: "error".into(),
+                message: "Failed to fetch apps".into(),
+                returneddata: None,
+            })
+        }
+    }
+}
@@ -0,0 +1 @@
+pub mod catalog;
\ No newline at end of file
@@ -1,20 +1,28 @@
-use actix_web::HttpResponse;
 use bcrypt::verify;
 use chrono::{Utc, Duration as ChronoDuration};
 use futures::stream::TryStreamExt;
 use mongodb::{
     bson::{doc, oid::ObjectId, Document, DateTime as BsonDateTime, Bson},
-    options::{ClientOptions, IndexOptions as CreateIndexOptions},
+    options::{ClientOptions, FindOptions, IndexOptions as CreateIndexOptions},
     Client, Collection, IndexModel,
 };
 
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::error::Error;
 use std::fs;
 
-use crate::{deploy::ApiResponse, utils::{settings::cloudprovider::ProviderRequest, user::signup_func::init_mongo_client}};
+use crate::utils::providers::cloud_provider::LEGACY_PROVIDER;
+use crate::utils::settings::credentials::CloudCredential;
+use crate::utils::settings::keyring::SealedSecret;
 use crate::utils::deployment::deploy::DeploymentRequest;
+use crate::utils::deployment::lifecycle::{DeploymentState, TransitionError};
+use crate::utils::deployment::operation_lock::LEASE_DURATION;
+use crate::utils::terraform::run_record::TerraformRun;
+use crate::utils::templates::versions::TemplateVersion;
 
+// How often a state transition re-reads the status after losing a race with another writer
+const TRANSITION_ATTEMPTS: usize = 3;
 
 #[derive(Deserialize)]
 struct Config {
@@ -88,6 +96,46 @@ pub async fn create_indexes(client: &Client) {
     collection.create_index(
         unique_index, None
     ).await.expect("Failed to create session token index");
+
+    // Published template versions are immutable, so a name/version pair can only ever exist once
+    let version_index = IndexModel::builder()
+        .keys(doc! { "name": 1, "version": 1 })
+        .options(CreateIndexOptions::builder().unique(true).build())
+        .build();
+
+    client.database("deploy").collection::<Document>("template_versions").create_index(
+        version_index, None
+    ).await.expect("Failed to create template version index");
+
+    // A user's credential names only have to be unique per provider
+    let credential_name_index = IndexModel::builder()
+        .keys(doc! { "user_id": 1, "provider": 1, "name": 1 })
+        .options(CreateIndexOptions::builder().unique(true).build())
+        .build();
+    let credential_id_index = IndexModel::builder()
+        .keys(doc! { "credential_id": 1 })
+        .options(CreateIndexOptions::builder().unique(true).build())
+        .build();
+
+    client.database("deploy").collection::<Document>("cloud_credentials").create_indexes(
+        [credential_name_index, credential_id_index], None
+    ).await.expect("Failed to create cloud credential indexes");
+
+    // A job computes its changes from the deployment as it was when queued, so only one may be in flight per deployment
+    let active_job_index = IndexModel::builder()
+        .keys(doc! { "project_id": 1 })
+        .options(
+            CreateIndexOptions::builder()
+                .name("one_active_job_per_deployment".to_string())
+                .unique(true)
+                .partial_filter_expression(doc! { "active": true })
+                .build(),
+        )
+        .build();
+
+    client.database("deploy").collection::<Document>("jobs").create_index(
+        active_job_index, None
+    ).await.expect("Failed to create active job index");
 }
 
 
@@ -145,8 +193,15 @@ pub async fn get_deployments_by_user_id(mongo_client: Client, user_id: &ObjectId
     let mongo_database = mongo_client.database("deploy");
     let deployment_collection = mongo_database.collection::<mongodb::bson::Document>("deployments");
 
-    let filter = doc! { "user_id": user_id };
-    let mut cursor = deployment_collection.find(filter, None).await?;
+    let filter = doc! {
+        "user_id": user_id,
+        "status": { "$ne": DeploymentState::Destroyed.as_str() },
+    };
+    // Sealed or not, cloud-init carries app passwords and never goes back to the client
+    let options = FindOptions::builder()
+        .projection(doc! { "sealed_user_data": 0, "variables.user_data": 0, "pending_upgrade.variables.user_data": 0 })
+        .build();
+    let mut cursor = deployment_collection.find(filter, options).await?;
 
     let mut deployments = Vec::new();
     while let Some(doc) = cursor.try_next().await? {
@@ -175,178 +230,692 @@ pub async fn connect_to_mongo() -> Result<Client, Box<dyn Error>> {
     Ok(client)
 }
 
-pub async fn store_deployment_metadata(client: Client, request: &DeploymentRequest, project_id: &str) -> Result<(), HttpResponse> {
-    match find_user_by_email(client.clone(), &request.user_email).await {
-        Ok(Some(user_doc)) => {
-            let user_id = user_doc.get_object_id("_id").map_err(|_| {
-                HttpResponse::InternalServerError().json(ApiResponse {
-                    status: "error".into(),
-                    message: "Failed to extract user ID".into(),
-                    returneddata: None,
-                })
-            })?;
-
-            let db = client.database("deploy");
-            let coll = db.collection("deployments");
-
-            let doc = doc! {
-                "project_id": project_id,
-                "project_name": &request.project_name,
-                "selected_service": &request.selected_service,
-                "selected_server": &request.selected_server,
-                "region": &request.region,
-                "volume_size": request.volume_size,
-                "ip_option": &request.ip_option,
-                "ssh_key": &request.ssh_key,
-                "terraform_template": &request.terraform_template,
-                "status": "initiated",
-                "timestamp": Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
-                "user_id": Bson::ObjectId(user_id),
-            };
-
-            coll.insert_one(doc, None).await.map_err(|e| {
-                eprintln!("❌ MongoDB insert error: {}", e);
-                HttpResponse::InternalServerError().json(ApiResponse {
-                    status: "error".into(),
-                    message: "Failed to save metadata".into(),
-                    returneddata: None,
-                })
-            })?;
-            println!("✅ Metadata saved to MongoDB");
-            println!("----------------------------------------");
-            Ok(())
+// The deployment is inserted already locked by `lock_holder`, so nothing can start on it before its deploy job takes the lock over
+#[allow(clippy::too_many_arguments)]
+pub async fn store_deployment_metadata(client: Client, request: &DeploymentRequest, provider: &str, project_id: &str, user_id: &ObjectId, template: &TemplateVersion, variables: &serde_json::Map<String, serde_json::Value>, lock_holder: &str) -> Result<(), mongodb::error::Error> {
+    let db = client.database("deploy");
+    let coll = db.collection("deployments");
+    let now = Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
+
+    let doc = doc! {
+        "project_id": project_id,
+        "project_name": &request.project_name,
+        "selected_service": &request.selected_service,
+        "provider": provider,
+        "credential_id": &request.credential_id,
+        "selected_server": &request.selected_server,
+        "region": &request.region,
+        "volume_size": request.volume_size,
+        "ip_option": &request.ip_option,
+        "ssh_key": &request.ssh_key,
+        "terraform_template": &template.name,
+        "app_id": &request.app_id,
+        "template_version": mongodb::bson::to_bson(template)?,
+        "variables": mongodb::bson::to_bson(variables)?,
+        "status": DeploymentState::Queued.as_str(),
+        "status_reason": "Deployment requested",
+        "transitions": [{
+            "from": Bson::Null,
+            "to": DeploymentState::Queued.as_str(),
+            "reason": "Deployment requested",
+            "at": &now,
+        }],
+        "timestamp": &now,
+        "updated_at": &now,
+        "user_id": Bson::ObjectId(*user_id),
+        "lock": lock_document(lock_holder, "deploy", LEASE_DURATION),
+    };
+
+    if let Err(e) = coll.insert_one(doc, None).await {
+        eprintln!("❌ MongoDB insert error: {}", e);
+        return Err(e);
+    }
+
+    println!("✅ Metadata saved to MongoDB");
+    println!("----------------------------------------");
+    Ok(())
+}
+
+
+pub async fn find_deployment(mongo_client: Client, user_id: &ObjectId, project_id: &str) -> Result<Option<Document>, mongodb::error::Error> {
+    let coll: Collection<Document> = mongo_client.database("deploy").collection("deployments");
+
+    let filter = doc! {
+        "user_id": Bson::ObjectId(*user_id),
+        "project_id": project_id,
+    };
+
+    coll.find_one(filter, None).await
+}
+
+// Moves a deployment into `to` only if its current state allows it, recording when and why
+pub async fn transition_deployment_state(mongo_client: Client, project_id: &str, to: DeploymentState, reason: &str) -> Result<(), TransitionError> {
+    let coll: Collection<Document> = mongo_client.database("deploy").collection("deployments");
+
+    // Another writer can change the status between the read and the update; the read is simply repeated then
+    let mut from = String::new();
+    for _ in 0..TRANSITION_ATTEMPTS {
+        let current = coll
+            .find_one(doc! { "project_id": project_id }, None)
+            .await
+            .map_err(TransitionError::Database)?
+            .ok_or(TransitionError::NotFound)?;
+        from = current.get_str("status").unwrap_or("unknown").to_string();
+
+        if !to.allowed_sources().iter().any(|state| state.as_str() == from) {
+            return Err(TransitionError::Illegal { from, to });
         }
-        Ok(None) => Err(HttpResponse::BadRequest().json(ApiResponse {
-            status: "error".into(),
-            message: "User not found with provided email".into(),
-            returneddata: None,
-        })),
-        Err(e) => {
-            eprintln!("❌ MongoDB error: {}", e);
-            Err(HttpResponse::InternalServerError().json(ApiResponse {
-                status: "error".into(),
-                message: "Failed to query user".into(),
-                returneddata: None,
-            }))
+
+        // Filtering on the status that was read makes the recorded `from` exactly the state that was left
+        let now = Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
+        let filter = doc! {
+            "project_id": project_id,
+            "status": &from,
+        };
+        let update = doc! {
+            "$set": {
+                "status": to.as_str(),
+                "status_reason": reason,
+                "updated_at": &now,
+            },
+            "$push": {
+                "transitions": {
+                    "from": &from,
+                    "to": to.as_str(),
+                    "reason": reason,
+                    "at": &now,
+                }
+            }
+        };
+
+        let result = coll.update_one(filter, update, None).await.map_err(TransitionError::Database)?;
+        if result.matched_count == 1 {
+            println!("🔀 Deployment {}: {} → {} ({})", project_id, from, to, reason);
+            println!("----------------------------------------");
+            return Ok(());
         }
     }
+
+    Err(TransitionError::Illegal { from, to })
 }
 
+// The `lock` field of a deployment held by `holder_id` from now until the lease runs out
+fn lock_document(holder_id: &str, operation: &str, lease: std::time::Duration) -> Document {
+    let now = Utc::now();
+    doc! {
+        "holder_id": holder_id,
+        "operation": operation,
+        "acquired_at": now.format("%Y-%m-%d %H:%M:%S").to_string(),
+        "expires_at": BsonDateTime::from_system_time(std::time::SystemTime::from(now) + lease),
+    }
+}
 
-pub async fn delete_deployment(mongo_client: Client,user_id_str: &str,project_id: &str) -> Result<HttpResponse, HttpResponse> {
-    
-    let db = mongo_client.database("deploy");
-    let coll: Collection<Document> = db.collection("deployments");
-
-    let user_id = match ObjectId::parse_str(user_id_str) {
-        Ok(oid) => oid,
-        Err(_) => {
-            return Err(HttpResponse::BadRequest().json(ApiResponse {
-                status: "error".to_string(),
-                message: "Invalid user ID format".to_string(),
-                returneddata: None,
-            }));
-        }
-    };
+// Takes the deployment's operation lock if nobody holds it or the previous holder's lease has run out
+pub async fn try_acquire_deployment_lock(mongo_client: Client, project_id: &str, holder_id: &str, operation: &str, lease: std::time::Duration) -> Result<bool, mongodb::error::Error> {
+    let coll: Collection<Document> = mongo_client.database("deploy").collection("deployments");
+    let now = Utc::now();
 
     let filter = doc! {
-        "user_id": Bson::ObjectId(user_id),
         "project_id": project_id,
+        "$or": [
+            { "lock": { "$exists": false } },
+            { "lock": Bson::Null },
+            { "lock.expires_at": { "$lt": BsonDateTime::from_system_time(now.into()) } },
+        ],
     };
+    let update = doc! { "$set": { "lock": lock_document(holder_id, operation, lease) } };
 
-    match coll.delete_one(filter, None).await {
-        Ok(result) if result.deleted_count == 1 => Ok(HttpResponse::Ok().json(ApiResponse {
-            status: "success".to_string(),
-            message: format!("Deployment with project_id '{}' deleted successfully.", project_id),
-            returneddata: None,
-        })),
-        Ok(_) => Err(HttpResponse::NotFound().json(ApiResponse {
-            status: "error".to_string(),
-            message: "No matching deployment found to delete.".to_string(),
-            returneddata: None,
-        })),
-        Err(e) => {
-            eprintln!("MongoDB delete error: {}", e);
-            Err(HttpResponse::InternalServerError().json(ApiResponse {
-                status: "error".to_string(),
-                message: "Failed to delete deployment".to_string(),
-                returneddata: None,
-            }))
+    let result = coll.update_one(filter, update, None).await?;
+    Ok(result.matched_count == 1)
+}
+
+pub async fn renew_deployment_lock(mongo_client: Client, project_id: &str, holder_id: &str, lease: std::time::Duration) -> Result<bool, mongodb::error::Error> {
+    let coll: Collection<Document> = mongo_client.database("deploy").collection("deployments");
+
+    let filter = doc! { "project_id": project_id, "lock.holder_id": holder_id };
+    let update = doc! {
+        "$set": { "lock.expires_at": BsonDateTime::from_system_time(std::time::SystemTime::now() + lease) }
+    };
+
+    let result = coll.update_one(filter, update, None).await?;
+    Ok(result.matched_count == 1)
+}
+
+pub async fn release_deployment_lock(mongo_client: Client, project_id: &str, holder_id: &str) -> Result<(), mongodb::error::Error> {
+    let coll: Collection<Document> = mongo_client.database("deploy").collection("deployments");
+
+    // Matching on the holder means a lock that expired and was taken over is left alone
+    let filter = doc! { "project_id": project_id, "lock.holder_id": holder_id };
+    coll.update_one(filter, doc! { "$set": { "lock": Bson::Null } }, None).await?;
+    Ok(())
+}
+
+// Returns the operation holding the deployment's lock and when it started, if the deployment exists
+pub async fn find_deployment_lock(mongo_client: Client, project_id: &str) -> Result<Option<(String, String)>, mongodb::error::Error> {
+    let coll: Collection<Document> = mongo_client.database("deploy").collection("deployments");
+
+    let Some(deployment) = coll.find_one(doc! { "project_id": project_id }, None).await? else {
+        return Ok(None);
+    };
+
+    let lock = deployment.get_document("lock").ok();
+    let operation = lock.and_then(|l| l.get_str("operation").ok()).unwrap_or("unknown").to_string();
+    let acquired_at = lock.and_then(|l| l.get_str("acquired_at").ok()).unwrap_or("unknown").to_string();
+    Ok(Some((operation, acquired_at)))
+}
+
+pub async fn store_deployment_outputs(mongo_client: Client, project_id: &str, outputs: &serde_json::Map<String, serde_json::Value>) -> Result<(), mongodb::error::Error> {
+    let coll: Collection<Document> = mongo_client.database("deploy").collection("deployments");
+
+    let update = doc! {
+        "$set": {
+            "outputs": mongodb::bson::to_bson(outputs)?,
+            "updated_at": Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
         }
+    };
+
+    coll.update_one(doc! { "project_id": project_id }, update, None).await?;
+    Ok(())
+}
+
+pub async fn store_rollback_outcome(mongo_client: Client, project_id: &str, rollback: &serde_json::Value) -> Result<(), mongodb::error::Error> {
+    let coll: Collection<Document> = mongo_client.database("deploy").collection("deployments");
+
+    let update = doc! {
+        "$set": {
+            "rollback": mongodb::bson::to_bson(rollback)?,
+            "updated_at": Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        }
+    };
+
+    coll.update_one(doc! { "project_id": project_id }, update, None).await?;
+    Ok(())
+}
+
+// Deployments created before the lifecycle states existed were stored as "initiated" once apply had succeeded
+pub async fn migrate_legacy_deployment_status(mongo_client: &Client) {
+    let coll: Collection<Document> = mongo_client.database("deploy").collection("deployments");
+
+    let update = doc! {
+        "$set": {
+            "status": DeploymentState::Running.as_str(),
+            "status_reason": "Migrated from legacy 'initiated' status",
+        }
+    };
+
+    if let Err(e) = coll.update_many(doc! { "status": "initiated" }, update, None).await {
+        eprintln!("❌ Failed to migrate legacy deployment status: {}", e);
     }
 }
 
-pub async fn update_provider_handler(mongo_client: Client,request: &ProviderRequest) -> Result<HttpResponse, HttpResponse> {
-    
-    match find_user_by_email(mongo_client.clone(), &request.user_email).await {
-        Ok(Some(user_doc)) => {
-            let user_id = match user_doc.get_object_id("_id") {
-                Ok(id) => id,
-                Err(_) => {
-                    return Err(HttpResponse::InternalServerError().json(ApiResponse {
-                        status: "error".into(),
-                        message: "Failed to extract user ID".into(),
-                        returneddata: None,
-                    }));
-                }
-            };
+// Deployments left mid-flight by a previous process can never finish, so mark them failed at startup
+pub async fn fail_interrupted_deployments(mongo_client: &Client) {
+    let coll: Collection<Document> = mongo_client.database("deploy").collection("deployments");
+    let now = Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    let reason = "Interrupted by a backend restart";
 
-            let filter = doc! { "_id": user_id };
-            let update = doc! {
-                "$set": {
-                    "CloudProvider": &request.provider_key
+    let in_progress: Vec<&str> = DeploymentState::ALL
+        .iter()
+        .filter(|state| state.is_in_progress())
+        .map(|state| state.as_str())
+        .collect();
+
+    let mut cursor = match coll.find(doc! { "status": { "$in": in_progress } }, None).await {
+        Ok(cursor) => cursor,
+        Err(e) => {
+            eprintln!("❌ Failed to look up interrupted deployments: {}", e);
+            return;
+        }
+    };
+
+    while let Ok(Some(deployment)) = cursor.try_next().await {
+        let Ok(project_id) = deployment.get_str("project_id") else {
+            continue;
+        };
+        let from = deployment.get_str("status").unwrap_or("unknown");
+
+        let update = doc! {
+            "$set": {
+                "status": DeploymentState::Failed.as_str(),
+                "status_reason": reason,
+                "updated_at": &now,
+            },
+            "$push": {
+                "transitions": {
+                    "from": from,
+                    "to": DeploymentState::Failed.as_str(),
+                    "reason": reason,
+                    "at": &now,
                 }
-            };
-
-            let db = mongo_client.database("deploy");
-            let users = db.collection::<Document>("users");
-
-            if let Err(err) = users.update_one(filter, update, None).await {
-                eprintln!("❌ MongoDB update failed: {}", err);
-                return Err(HttpResponse::InternalServerError().json(ApiResponse {
-                    status: "error".into(),
-                    message: "Failed to update cloud provider key".into(),
-                    returneddata: None,
-                }));
             }
+        };
 
-            Ok(HttpResponse::Ok().json(ApiResponse {
-                status: "success".into(),
-                message: "Cloud provider token updated successfully".into(),
-                returneddata: None,
-            }))
+        if let Err(e) = coll.update_one(doc! { "project_id": project_id, "status": from }, update, None).await {
+            eprintln!("❌ Failed to mark deployment {} as failed: {}", project_id, e);
+        } else {
+            println!("⚠️ Deployment {} was left '{}', marked as failed", project_id, from);
         }
+    }
+}
 
-        Ok(None) => Err(HttpResponse::NotFound().json(ApiResponse {
-            status: "error".into(),
-            message: "User not found".into(),
-            returneddata: None,
-        })),
-
-        Err(err) => {
-            eprintln!("❌ DB error: {}", err);
-            Err(HttpResponse::InternalServerError().json(ApiResponse {
-                status: "error".into(),
-                message: "Database error".into(),
-                returneddata: None,
-            }))
+// Mongo's error code for an insert or update that breaks a unique index
+pub fn is_duplicate_key(error: &mongodb::error::Error) -> bool {
+    matches!(
+        error.kind.as_ref(),
+        mongodb::error::ErrorKind::Write(mongodb::error::WriteFailure::WriteError(e)) if e.code == 11000
+    )
+}
+
+pub async fn insert_cloud_credential(mongo_client: Client, credential: &CloudCredential) -> Result<(), mongodb::error::Error> {
+    let credentials = mongo_client.database("deploy").collection::<Document>("cloud_credentials");
+    credentials.insert_one(mongodb::bson::to_document(credential)?, None).await?;
+    Ok(())
+}
+
+pub async fn list_cloud_credentials(mongo_client: Client, user_id: &ObjectId) -> Result<Vec<CloudCredential>, mongodb::error::Error> {
+    let credentials = mongo_client.database("deploy").collection::<Document>("cloud_credentials");
+
+    let options = FindOptions::builder().sort(doc! { "provider": 1, "name": 1 }).build();
+    let mut cursor = credentials.find(doc! { "user_id": user_id }, options).await?;
+
+    let mut records = Vec::new();
+    while let Some(record) = cursor.try_next().await? {
+        records.push(mongodb::bson::from_document(record)?);
+    }
+
+    Ok(records)
+}
+
+pub async fn find_cloud_credential(mongo_client: Client, user_id: &ObjectId, credential_id: &str) -> Result<Option<CloudCredential>, mongodb::error::Error> {
+    let credentials = mongo_client.database("deploy").collection::<Document>("cloud_credentials");
+
+    match credentials.find_one(doc! { "user_id": user_id, "credential_id": credential_id }, None).await? {
+        Some(record) => Ok(Some(mongodb::bson::from_document(record)?)),
+        None => Ok(None),
+    }
+}
+
+// Only the given parts change; false when the user has no such credential
+pub async fn update_cloud_credential(mongo_client: Client, user_id: &ObjectId, credential_id: &str, name: Option<&str>, sealed: Option<(&SealedSecret, &HashMap<String, String>)>) -> Result<bool, mongodb::error::Error> {
+    let credentials = mongo_client.database("deploy").collection::<Document>("cloud_credentials");
+
+    let mut set = doc! { "updated_at": Utc::now().format("%Y-%m-%d %H:%M:%S").to_string() };
+    if let Some(name) = name {
+        set.insert("name", name);
+    }
+    if let Some((sealed, hints)) = sealed {
+        set.insert("sealed", mongodb::bson::to_bson(sealed)?);
+        set.insert("hints", mongodb::bson::to_bson(hints)?);
+    }
+
+    let result = credentials.update_one(doc! { "user_id": user_id, "credential_id": credential_id }, doc! { "$set": set }, None).await?;
+    Ok(result.matched_count > 0)
+}
+
+pub async fn delete_cloud_credential(mongo_client: Client, user_id: &ObjectId, credential_id: &str) -> Result<bool, mongodb::error::Error> {
+    let credentials = mongo_client.database("deploy").collection::<Document>("cloud_credentials");
+    let result = credentials.delete_one(doc! { "user_id": user_id, "credential_id": credential_id }, None).await?;
+    Ok(result.deleted_count > 0)
+}
+
+// Deployments that still have infrastructure a later destroy would need the credential for. Ones from before named
+// credentials have no credential_id and fall back to their owner's default, so `default_for` (owner, provider) counts those too
+pub async fn count_deployments_using_credential(mongo_client: Client, credential_id: &str, default_for: Option<(&ObjectId, &str)>) -> Result<u64, mongodb::error::Error> {
+    let coll: Collection<Document> = mongo_client.database("deploy").collection("deployments");
+
+    let mut uses = vec![doc! { "credential_id": credential_id }];
+    if let Some((user_id, provider)) = default_for {
+        // Deployments without a provider all ran the legacy provider's template
+        let provider_filter = if provider == LEGACY_PROVIDER {
+            doc! { "$in": [provider, Bson::Null] }
+        } else {
+            doc! { "$eq": provider }
+        };
+        uses.push(doc! { "user_id": user_id, "credential_id": Bson::Null, "provider": provider_filter });
+    }
+
+    coll.count_documents(doc! { "$or": uses, "status": { "$ne": DeploymentState::Destroyed.as_str() } }, None).await
+}
+
+pub async fn find_cloud_credential_by_name(mongo_client: Client, user_id: &ObjectId, provider: &str, name: &str) -> Result<Option<CloudCredential>, mongodb::error::Error> {
+    let credentials = mongo_client.database("deploy").collection::<Document>("cloud_credentials");
+
+    match credentials.find_one(doc! { "user_id": user_id, "provider": provider, "name": name }, None).await? {
+        Some(record) => Ok(Some(mongodb::bson::from_document(record)?)),
+        None => Ok(None),
+    }
+}
+
+// Credentials stored before encryption (plaintext `fields`) or sealed with a key that is no longer the active one
+pub async fn find_credentials_to_reseal(mongo_client: &Client, active_key_id: &str) -> Result<Vec<Document>, mongodb::error::Error> {
+    let credentials = mongo_client.database("deploy").collection::<Document>("cloud_credentials");
+
+    let filter = doc! { "$or": [{ "fields": { "$exists": true } }, { "sealed.key_id": { "$ne": active_key_id } }] };
+    let mut cursor = credentials.find(filter, None).await?;
+
+    let mut records = Vec::new();
+    while let Some(record) = cursor.try_next().await? {
+        records.push(record);
+    }
+
+    Ok(records)
+}
+
+// Swaps in a newly sealed value and drops any plaintext fields left from before encryption
+pub async fn store_resealed_credential(mongo_client: &Client, credential_id: &str, sealed: &SealedSecret, hints: &HashMap<String, String>) -> Result<(), mongodb::error::Error> {
+    let credentials = mongo_client.database("deploy").collection::<Document>("cloud_credentials");
+
+    let update = doc! {
+        "$set": {
+            "sealed": mongodb::bson::to_bson(sealed)?,
+            "hints": mongodb::bson::to_bson(hints)?,
+        },
+        "$unset": { "fields": "" },
+    };
+
+    credentials.update_one(doc! { "credential_id": credential_id }, update, None).await?;
+    Ok(())
+}
+
+// Deployments whose cloud-init is still in plaintext, or sealed with a key other than the active one
+pub async fn find_user_data_to_seal(mongo_client: &Client, active_key_id: &str) -> Result<Vec<Document>, mongodb::error::Error> {
+    let deployments = mongo_client.database("deploy").collection::<Document>("deployments");
+
+    let filter = doc! { "$or": [
+        { "variables.user_data": { "$exists": true } },
+        { "pending_upgrade.variables.user_data": { "$exists": true } },
+        { "sealed_user_data": { "$exists": true }, "sealed_user_data.key_id": { "$ne": active_key_id } },
+    ] };
+    let mut cursor = deployments.find(filter, None).await?;
+
+    let mut records = Vec::new();
+    while let Some(record) = cursor.try_next().await? {
+        records.push(record);
+    }
+
+    Ok(records)
+}
+
+// Stores a deployment's sealed cloud-init and drops any plaintext copy left in its variables
+pub async fn store_sealed_user_data(mongo_client: &Client, project_id: &str, sealed: &SealedSecret) -> Result<(), mongodb::error::Error> {
+    let deployments = mongo_client.database("deploy").collection::<Document>("deployments");
+
+    let update = doc! {
+        "$set": { "sealed_user_data": mongodb::bson::to_bson(sealed)? },
+        "$unset": { "variables.user_data": "", "pending_upgrade.variables.user_data": "" },
+    };
+
+    deployments.update_one(doc! { "project_id": project_id }, update, None).await?;
+    Ok(())
+}
+
+// Before named credentials, each user had one Hetzner token in users.CloudProvider
+pub async fn find_legacy_cloud_provider_tokens(mongo_client: &Client) -> Result<Vec<(ObjectId, String)>, mongodb::error::Error> {
+    let users: Collection<Document> = mongo_client.database("deploy").collection("users");
+    let mut cursor = users.find(doc! { "CloudProvider": { "$type": "string" } }, None).await?;
+
+    let mut tokens = Vec::new();
+    while let Some(user) = cursor.try_next().await? {
+        if let (Ok(user_id), Ok(token)) = (user.get_object_id("_id"), user.get_str("CloudProvider")) {
+            tokens.push((user_id, token.to_string()));
+        }
+    }
+
+    Ok(tokens)
+}
+
+pub async fn remove_legacy_cloud_provider_token(mongo_client: &Client, user_id: &ObjectId) -> Result<(), mongodb::error::Error> {
+    let users: Collection<Document> = mongo_client.database("deploy").collection("users");
+    users.update_one(doc! { "_id": user_id }, doc! { "$unset": { "CloudProvider": "" } }, None).await?;
+    Ok(())
+}
+
+pub async fn create_job(mongo_client: Client, job_id: &str, project_id: &str, kind: &str, user_email: &str) -> Result<(), mongodb::error::Error> {
+    let jobs = mongo_client.database("deploy").collection::<Document>("jobs");
+    let now = Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
+
+    let job = doc! {
+        "job_id": job_id,
+        "project_id": project_id,
+        "kind": kind,
+        "user_email": user_email,
+        "status": "queued",
+        "active": true,
+        "message": "Waiting for a free deployment worker",
+        "created_at": &now,
+        "updated_at": &now,
+    };
+
+    // Fails with a duplicate key error while another job for the deployment is queued or running
+    jobs.insert_one(job, None).await?;
+    Ok(())
+}
+
+pub async fn update_job_status(mongo_client: Client, job_id: &str, status: &str, message: &str, details: Option<&serde_json::Value>) -> Result<(), mongodb::error::Error> {
+    let jobs = mongo_client.database("deploy").collection::<Document>("jobs");
+
+    let details = match details {
+        Some(details) => mongodb::bson::to_bson(details)?,
+        None => Bson::Null,
+    };
+
+    let mut update = doc! {
+        "$set": {
+            "status": status,
+            "message": message,
+            "details": details,
+            "updated_at": Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
         }
+    };
+    // A finished job no longer holds the deployment's slot
+    if !matches!(status, "queued" | "running") {
+        update.insert("$unset", doc! { "active": "" });
     }
+
+    jobs.update_one(doc! { "job_id": job_id }, update, None).await?;
+    Ok(())
 }
 
+pub async fn find_job_by_id(mongo_client: Client, job_id: &str) -> Result<Option<Document>, mongodb::error::Error> {
+    let jobs = mongo_client.database("deploy").collection::<Document>("jobs");
+    jobs.find_one(doc! { "job_id": job_id }, None).await
+}
+
+// Jobs only live in the in-memory queue, so anything still queued or running at startup was lost with the previous process
+pub async fn fail_interrupted_jobs(mongo_client: &Client) {
+    let jobs = mongo_client.database("deploy").collection::<Document>("jobs");
+
+    let filter = doc! { "status": { "$in": ["queued", "running"] } };
+    let update = doc! {
+        "$set": {
+            "status": "failed",
+            "message": "Interrupted by a backend restart",
+            "updated_at": Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        },
+        "$unset": { "active": "" },
+    };
 
+    match jobs.update_many(filter, update, None).await {
+        Ok(result) if result.modified_count > 0 => {
+            println!("⚠️ Marked {} interrupted job(s) as failed", result.modified_count);
+            println!("----------------------------------------");
+        }
+        Ok(_) => {}
+        Err(e) => eprintln!("❌ Failed to clean up interrupted jobs: {}", e),
+    }
+}
 
-pub async fn fetch_cloud_provider(email: &str) -> Result<String, Box<dyn Error>> {
-    let mongo_client = init_mongo_client().await;
-    let user_result = find_user_by_email(mongo_client, email).await?;
+pub async fn insert_terraform_run(mongo_client: Client, run: &TerraformRun, started_at: &str) -> Result<(), mongodb::error::Error> {
+    let runs = mongo_client.database("deploy").collection::<Document>("terraform_runs");
+
+    let record = doc! {
+        "run_id": &run.run_id,
+        "project_id": &run.project_id,
+        "command": &run.command,
+        "status": "running",
+        "exit_code": Bson::Null,
+        "error": Bson::Null,
+        "log_key": &run.log_key,
+        "started_at": started_at,
+        "finished_at": Bson::Null,
+    };
 
-    if let Some(user_doc) = user_result {
-        match user_doc.get("CloudProvider") {
-            Some(Bson::String(cloud_key)) => Ok(cloud_key.to_string()),
-            Some(Bson::Null) | None => Err("Cloud provider not set for this user.".into()),
-            _ => Err("Cloud provider field is in an unexpected format.".into()),
+    runs.insert_one(record, None).await?;
+    Ok(())
+}
+
+pub async fn complete_terraform_run(mongo_client: Client, run_id: &str, status: &str, exit_code: Option<i32>, error: Option<&str>, finished_at: &str) -> Result<(), mongodb::error::Error> {
+    let runs = mongo_client.database("deploy").collection::<Document>("terraform_runs");
+
+    let update = doc! {
+        "$set": {
+            "status": status,
+            "exit_code": exit_code,
+            "error": error,
+            "finished_at": finished_at,
+        }
+    };
+
+    runs.update_one(doc! { "run_id": run_id }, update, None).await?;
+    Ok(())
+}
+
+pub async fn list_terraform_runs(mongo_client: Client, project_id: &str) -> Result<Vec<Document>, mongodb::error::Error> {
+    let runs = mongo_client.database("deploy").collection::<Document>("terraform_runs");
+
+    let options = FindOptions::builder().sort(doc! { "started_at": -1 }).projection(doc! { "_id": 0 }).build();
+    let mut cursor = runs.find(doc! { "project_id": project_id }, options).await?;
+
+    let mut records = Vec::new();
+    while let Some(record) = cursor.try_next().await? {
+        records.push(record);
+    }
+
+    Ok(records)
+}
+
+pub async fn find_terraform_run(mongo_client: Client, project_id: &str, run_id: &str) -> Result<Option<Document>, mongodb::error::Error> {
+    let runs = mongo_client.database("deploy").collection::<Document>("terraform_runs");
+    runs.find_one(doc! { "project_id": project_id, "run_id": run_id }, None).await
+}
+
+// Records name/version as being published before any file is copied; the unique index makes this the claim,
+// so false means someone else already owns that version
+pub async fn claim_template_version(mongo_client: Client, name: &str, version: &str) -> Result<bool, mongodb::error::Error> {
+    let versions = mongo_client.database("deploy").collection::<Document>("template_versions");
+
+    let claim = doc! {
+        "name": name,
+        "version": version,
+        "publishing": true,
+        "claimed_at": Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+    };
+
+    match versions.insert_one(claim, None).await {
+        Ok(_) => Ok(true),
+        Err(e) if is_duplicate_key(&e) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+// Turns a claim into a published version once its files are copied and hashed
+pub async fn complete_template_version(mongo_client: Client, version: &TemplateVersion) -> Result<(), mongodb::error::Error> {
+    let versions = mongo_client.database("deploy").collection::<Document>("template_versions");
+
+    let update = doc! {
+        "$set": { "hash": &version.hash, "published_at": &version.published_at },
+        "$unset": { "publishing": "", "claimed_at": "" },
+    };
+    versions
+        .update_one(doc! { "name": &version.name, "version": &version.version, "publishing": true }, update, None)
+        .await?;
+    Ok(())
+}
+
+pub async fn release_template_version_claim(mongo_client: Client, name: &str, version: &str) -> Result<(), mongodb::error::Error> {
+    let versions = mongo_client.database("deploy").collection::<Document>("template_versions");
+    versions.delete_one(doc! { "name": name, "version": version, "publishing": true }, None).await?;
+    Ok(())
+}
+
+// A publish cut short by a restart would otherwise hold its version forever
+pub async fn release_interrupted_template_claims(mongo_client: &Client) {
+    let versions = mongo_client.database("deploy").collection::<Document>("template_versions");
+
+    match versions.delete_many(doc! { "publishing": true }, None).await {
+        Ok(result) if result.deleted_count > 0 => {
+            println!("⚠️ Released {} interrupted template publish(es)", result.deleted_count);
+            println!("----------------------------------------");
         }
-    } else {
-        Err("User not found.".into())
+        Ok(_) => {}
+        Err(e) => eprintln!("❌ Failed to release interrupted template publishes: {}", e),
     }
-}
\ No newline at end of file
+}
+
+// With no version given, the most recently published one is returned
+pub async fn find_template_version(mongo_client: Client, name: &str, version: Option<&str>) -> Result<Option<TemplateVersion>, mongodb::error::Error> {
+    let versions = mongo_client.database("deploy").collection::<Document>("template_versions");
+
+    // Versions still being published are claims, not versions anyone can deploy
+    let mut filter = doc! { "name": name, "publishing": { "$exists": false } };
+    if let Some(version) = version {
+        filter.insert("version", version);
+    }
+    let options = mongodb::options::FindOneOptions::builder().sort(doc! { "published_at": -1 }).build();
+
+    match versions.find_one(filter, options).await? {
+        Some(record) => Ok(Some(mongodb::bson::from_document(record)?)),
+        None => Ok(None),
+    }
+}
+
+pub async fn list_template_versions(mongo_client: Client, name: &str) -> Result<Vec<Document>, mongodb::error::Error> {
+    let versions = mongo_client.database("deploy").collection::<Document>("template_versions");
+
+    let options = FindOptions::builder().sort(doc! { "published_at": -1 }).projection(doc! { "_id": 0 }).build();
+    let mut cursor = versions.find(doc! { "name": name, "publishing": { "$exists": false } }, options).await?;
+
+    let mut records = Vec::new();
+    while let Some(record) = cursor.try_next().await? {
+        records.push(record);
+    }
+
+    Ok(records)
+}
+
+// Remembers what an upgrade was planned against, so it can only be applied after that plan was shown
+pub async fn store_pending_upgrade(mongo_client: Client, project_id: &str, template: &TemplateVersion, variables: &serde_json::Map<String, serde_json::Value>, plan_fingerprint: &str) -> Result<(), mongodb::error::Error> {
+    let coll: Collection<Document> = mongo_client.database("deploy").collection("deployments");
+
+    let update = doc! {
+        "$set": {
+            "pending_upgrade": {
+                "template_version": mongodb::bson::to_bson(template)?,
+                "variables": mongodb::bson::to_bson(variables)?,
+                "plan_fingerprint": plan_fingerprint,
+                "planned_at": Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            },
+            "updated_at": Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        }
+    };
+
+    coll.update_one(doc! { "project_id": project_id }, update, None).await?;
+    Ok(())
+}
+
+// Records the template version and variables a deployment's infrastructure now matches
+pub async fn store_deployment_configuration(mongo_client: Client, project_id: &str, template: &TemplateVersion, variables: &serde_json::Map<String, serde_json::Value>) -> Result<(), mongodb::error::Error> {
+    let coll: Collection<Document> = mongo_client.database("deploy").collection("deployments");
+
+    let update = doc! {
+        "$set": {
+            "template_version": mongodb::bson::to_bson(template)?,
+            "variables": mongodb::bson::to_bson(variables)?,
+            "updated_at": Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        },
+        "$unset": { "pending_upgrade": "" },
+    };
+
+    coll.update_one(doc! { "project_id": project_id }, update, None).await?;
+    Ok(())
+}
@@ -0,0 +1,233 @@
+use std::collections::{HashMap, HashSet};
+use mongodb::{bson::Document, Client};
+use serde_json::{json, Map, Value};
+
+use crate::{
+    deploy::{deployment_prefix, DeploymentRequest},
+    s3_handler::render_checked,
+    utils::apps::catalog::{check_server_size, find_app, resolve_app_parameters, AppDefinition, COMPOSE_PREFIX},
+    utils::database::db::{find_user_data_to_seal, store_sealed_user_data},
+    utils::deployment::firewall::{app_firewall_rules, FIREWALL_RULES_VARIABLE},
+    utils::providers::cloud_provider::CloudProvider,
+    utils::settings::keyring::{Keyring, SealedSecret},
+    utils::storage::artifact_store::ArtifactStore,
+    utils::templates::catalog::TemplateManifest,
+    utils::terraform::variables::{upload_tfvars, TerraformSecrets, TFVARS_FILE},
+};
+
+// Template variable the generated document is handed to; templates that can run apps declare it
+pub const USER_DATA_VARIABLE: &str = "user_data";
+
+// Everything lands here on the server; compose-up.sh runs `docker compose` in its working directory
+const APP_DIR: &str = "/opt/app";
+
+// Provisioning scripts in the order they run, as (store key, file name on the server)
+const SETUP_SCRIPTS: [(&str, &str); 2] = [
+    ("scripts/env/base.sh", "base.sh"),
+    ("scripts/env/docker.sh", "docker.sh"),
+];
+const COMPOSE_UP_SCRIPT: (&str, &str) = ("scripts/compose-up.sh", "compose-up.sh");
+
+async fn read_text(store: &dyn ArtifactStore, key: &str) -> Result<Option<String>, String> {
+    match store.get(key).await {
+        Ok(Some(bytes)) => String::from_utf8(bytes)
+            .map(Some)
+            .map_err(|_| format!("{} is not valid UTF-8", key)),
+        Ok(None) => Ok(None),
+        Err(e) => {
+            eprintln!("❌ Failed to read {}: {}", key, e);
+            Err(format!("Failed to read {}", key))
+        }
+    }
+}
+
+fn write_file(name: &str, content: String, permissions: &str) -> Value {
+    json!({
+        "path": format!("{}/{}", APP_DIR, name),
+        "permissions": permissions,
+        "content": content,
+    })
+}
+
+// A `#cloud-config` document that installs Docker and brings up the app's compose stack on first boot.
+// JSON is valid YAML, so serde does the quoting and no script or compose content can break the document.
+pub async fn build_cloud_init(store: &dyn ArtifactStore, app: &AppDefinition, replacements: &HashMap<String, String>) -> Result<String, String> {
+    let compose_key = format!("{}{}", COMPOSE_PREFIX, app.compose_file);
+    let compose = read_text(store, &compose_key)
+        .await?
+        .ok_or_else(|| format!("Compose file {} for app '{}' is missing from the store", compose_key, app.id))?;
+
+    let mut used = HashSet::new();
+    let compose = render_checked(&compose_key, &compose, replacements, &mut used).map_err(|e| e.to_string())?;
+
+    let mut files = vec![write_file("compose.yaml", compose, "0644")];
+    let mut commands = Vec::new();
+
+    for (key, name) in SETUP_SCRIPTS.iter().chain([&COMPOSE_UP_SCRIPT]) {
+        let script = read_text(store, key)
+            .await?
+            .ok_or_else(|| format!("Provisioning script {} is missing from the store", key))?;
+        files.push(write_file(name, script, "0755"));
+        commands.push(format!("cd {} && DEBIAN_FRONTEND=noninteractive bash {}", APP_DIR, name));
+    }
+
+    let document = json!({
+        "write_files": files,
+        "runcmd": commands,
+    });
+
+    println!("☁️ Built cloud-init for app '{}' from {}", app.id, compose_key);
+    println!("--------------------------------------------------------");
+
+    serde_json::to_string_pretty(&document)
+        .map(|document| format!("#cloud-config\n{}\n", document))
+        .map_err(|e| format!("Failed to build cloud-init: {}", e))
+}
+
+// Variables a request's app adds to the template's, empty when no app was chosen
+pub async fn app_variables(store: &dyn ArtifactStore, provider: &dyn CloudProvider, manifest: &TemplateManifest, deploymentrequest: &DeploymentRequest) -> Result<Map<String, Value>, String> {
+    let mut variables = Map::new();
+    let Some(app_id) = deploymentrequest.app_id.as_deref() else {
+        if !deploymentrequest.app_parameters.is_empty() {
+            return Err("app_parameters were given without an app_id".into());
+        }
+        return Ok(variables);
+    };
+
+    let app = match find_app(store, app_id).await {
+        Ok(Some(app)) => app,
+        Ok(None) => return Err(format!("Unknown app_id '{}'", app_id)),
+        Err(e) => {
+            eprintln!("❌ Error loading app catalog: {}", e);
+            return Err("Failed to load the app catalog".into());
+        }
+    };
+
+    if !manifest.app_types.contains(&app.app_type) || !manifest.variables.iter().any(|variable| variable.name == USER_DATA_VARIABLE) {
+        return Err(format!("Template '{}' cannot run {} apps like '{}'", manifest.name, app.app_type, app.id));
+    }
+
+    check_server_size(&app, provider, &deploymentrequest.selected_server)?;
+    let replacements = resolve_app_parameters(&app, &deploymentrequest.app_parameters)?;
+
+    let user_data = build_cloud_init(store, &app, &replacements).await?;
+    variables.insert(USER_DATA_VARIABLE.into(), Value::String(user_data));
+
+    // Opens exactly the ports the app listens on instead of the template's generic web defaults
+    if manifest.variables.iter().any(|variable| variable.name == FIREWALL_RULES_VARIABLE) {
+        let rules = serde_json::to_value(app_firewall_rules(&app)).map_err(|e| format!("Failed to build firewall rules: {}", e))?;
+        variables.insert(FIREWALL_RULES_VARIABLE.into(), rules);
+    }
+
+    Ok(variables)
+}
+
+// Cloud-init carries app passwords, so it is sealed like a credential and tied to its deployment
+fn user_data_context(project_id: &str) -> String {
+    format!("{}:{}", USER_DATA_VARIABLE, project_id)
+}
+
+pub fn seal_user_data(keyring: &Keyring, project_id: &str, user_data: &str) -> Result<SealedSecret, String> {
+    keyring.seal(user_data.as_bytes(), user_data_context(project_id).as_bytes())
+}
+
+fn open_user_data(keyring: &Keyring, project_id: &str, sealed: &SealedSecret) -> Result<String, String> {
+    let plaintext = keyring.open(sealed, user_data_context(project_id).as_bytes())?;
+    String::from_utf8(plaintext).map_err(|_| "Sealed user_data is not valid UTF-8".to_string())
+}
+
+// The sealed cloud-init a deployment was created with, if it runs an app
+pub fn deployment_user_data(deployment: &Document) -> Option<SealedSecret> {
+    mongodb::bson::from_document(deployment.get_document("sealed_user_data").ok()?.clone()).ok()
+}
+
+// Secret names to validate a deployment's variables with: the provider's credentials, plus user_data when it has one
+pub fn secret_variable_names(provider: &dyn CloudProvider, user_data: bool) -> Vec<&'static str> {
+    let mut names = provider.secret_variables().to_vec();
+    if user_data {
+        names.push(USER_DATA_VARIABLE);
+    }
+    names
+}
+
+// Opens the deployment's cloud-init into the secrets, right before Terraform runs
+pub fn add_user_data(secrets: &mut TerraformSecrets, keyring: &Keyring, project_id: &str, sealed: Option<&SealedSecret>) -> Result<(), String> {
+    let Some(sealed) = sealed else {
+        return Ok(());
+    };
+
+    let user_data = open_user_data(keyring, project_id, sealed).map_err(|e| {
+        eprintln!("❌ Failed to open user_data for {}: {}", project_id, e);
+        "The deployment's cloud-init could not be decrypted".to_string()
+    })?;
+    secrets.insert(USER_DATA_VARIABLE.into(), user_data);
+    Ok(())
+}
+
+// Cloud-init of a deployment that still needs sealing under the active key: plaintext from before it was sealed, or sealed with an older key
+fn stored_user_data(keyring: &Keyring, project_id: &str, deployment: &Document) -> Result<String, String> {
+    let plaintext = ["variables", "pending_upgrade.variables"].iter().find_map(|path| {
+        let mut variables = deployment;
+        for part in path.split('.') {
+            variables = variables.get_document(part).ok()?;
+        }
+        variables.get_str(USER_DATA_VARIABLE).ok()
+    });
+    if let Some(user_data) = plaintext {
+        return Ok(user_data.to_string());
+    }
+
+    let sealed = deployment_user_data(deployment).ok_or("Deployment has no user_data")?;
+    open_user_data(keyring, project_id, &sealed)
+}
+
+// Drops user_data from a tfvars file written before it was passed as TF_VAR_user_data
+async fn scrub_tfvars(store: &dyn ArtifactStore, prefix: &str) -> Result<(), String> {
+    let key = format!("{}{}", prefix, TFVARS_FILE);
+    let Some(bytes) = store.get(&key).await.map_err(|e| e.to_string())? else {
+        return Ok(());
+    };
+
+    let mut variables: Map<String, Value> = serde_json::from_slice(&bytes).map_err(|e| format!("Invalid {}: {}", key, e))?;
+    if variables.remove(USER_DATA_VARIABLE).is_some() {
+        upload_tfvars(store, prefix, &variables).await.map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+// Seals cloud-init stored in plaintext and re-seals values under retired keys, next to the credential migration
+pub async fn migrate_deployment_user_data(mongo_client: &Client, store: &dyn ArtifactStore, keyring: &Keyring) {
+    let deployments = match find_user_data_to_seal(mongo_client, keyring.active_key_id()).await {
+        Ok(deployments) => deployments,
+        Err(e) => {
+            eprintln!("❌ Failed to look up deployments with user_data to seal: {}", e);
+            return;
+        }
+    };
+
+    for deployment in deployments {
+        let Ok(project_id) = deployment.get_str("project_id") else {
+            continue;
+        };
+
+        let sealed = match stored_user_data(keyring, project_id, &deployment).and_then(|user_data| seal_user_data(keyring, project_id, &user_data)) {
+            Ok(sealed) => sealed,
+            Err(e) => {
+                eprintln!("❌ Failed to seal user_data for {}: {}", project_id, e);
+                continue;
+            }
+        };
+
+        if let Ok(project_name) = deployment.get_str("project_name") {
+            if let Err(e) = scrub_tfvars(store, &deployment_prefix(project_name, project_id)).await {
+                eprintln!("❌ Failed to remove user_data from the tfvars of {}: {}", project_id, e);
+                continue;
+            }
+        }
+
+        match store_sealed_user_data(mongo_client, project_id, &sealed).await {
+            Ok(()) => println!("🔑 Sealed user_data of {} with key '{}'", project_id, sealed.key_id),
+            Err(e) => eprintln!("❌ Failed to store sealed user_data for {}: {}", project_id, e),
+        }
+    }
+}
@@ -1,15 +1,33 @@
 use actix_web::{web, HttpResponse, Responder};
-use std::collections::HashMap;
-use aws_config::BehaviorVersion;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use mongodb::{bson::{oid::ObjectId, Document}, Client};
+use serde_json::json;
+use chrono::Utc;
+
 use crate::{
-    app_config::AppConfig,
+    app_config::{AppConfig, RollbackPolicy},
     s3_handler::{copy_and_transform_files, delete_specific_deployment_folder},
-    utils::database::db::{store_deployment_metadata, find_user_by_email, delete_deployment, fetch_cloud_provider},
+    utils::apps::catalog::AppParameterValues,
+    utils::database::db::{store_deployment_metadata, store_sealed_user_data, find_user_by_email, find_deployment, transition_deployment_state, store_deployment_outputs, store_rollback_outcome, create_job, update_job_status},
+    utils::deployment::cloud_init::{add_user_data, app_variables, seal_user_data, secret_variable_names, USER_DATA_VARIABLE},
+    utils::deployment::ssh_key::{validate_public_key, SSH_PUBLIC_KEY_VARIABLE},
+    utils::deployment::lifecycle::{DeploymentState, TransitionError},
+    utils::deployment::operation_lock::{LockError, OperationLock},
+    utils::providers::cloud_provider::{find_provider, CloudProvider},
+    utils::settings::credentials::CredentialRef,
+    utils::settings::keyring::Keyring,
+    utils::storage::artifact_store::ArtifactStore,
+    utils::templates::catalog::{read_manifest, TemplateManifest},
+    utils::templates::versions::{resolve_template_version, template_version_prefix, verify_template_version, TemplateVersion},
+    utils::terraform::variables::{placeholder_replacements, upload_tfvars, validate_variables, TerraformSecrets},
+    utils::jobs::job_queue::{DeployJob, Job, JobFailure, JobQueue},
     utils::user::signup_func::init_mongo_client,
-    terraform_handler::{execute_deployment,destroy_terraform_resources},
+    terraform_handler::{execute_deployment,destroy_terraform_resources,upload_backend_config,state_prefix},
+    utils::terraform::diagnostics::terraform_error_details,
+    utils::terraform::log_stream::{LogChannel, LogHub},
+    utils::terraform::run_record::{finish_terraform_run, start_terraform_run, RunLog},
 };
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -23,8 +41,25 @@ pub struct DeploymentRequest {
     pub ip_option: String,
     pub ssh_key_option: Option<String>,
     pub ssh_key: Option<String>,
+    // The provider's default template when left out
+    #[serde(default)]
     pub terraform_template: String,
+    // Self-hosted app from the app catalog to start on the server once it boots
+    #[serde(default)]
+    pub app_id: Option<String>,
+    // Values for the app's parameters, e.g. WG_HOST for wg-easy
+    #[serde(default)]
+    pub app_parameters: AppParameterValues,
+    // Published version to deploy; the latest one when left out
+    #[serde(default)]
+    pub template_version: Option<String>,
     pub user_email: String,
+    // One of the user's credentials for the provider; their default one when left out
+    #[serde(default)]
+    pub credential_id: Option<String>,
+    // Extra values for variables the template declares beyond the ones derived from the fields above
+    #[serde(default)]
+    pub variables: serde_json::Map<String, serde_json::Value>,
 }
 
 #[derive(Serialize)]
@@ -38,17 +73,24 @@ pub struct ApiResponse {
 pub struct UndeployRequest {
     pub user_email: String,      
     pub project_id: String,   
-    pub project_name: String, 
 }
 
-pub fn validate_request(data: &DeploymentRequest) -> Result<(), String> {
+// Checks the request and returns the provider it is for
+pub fn validate_request(data: &DeploymentRequest) -> Result<&'static dyn CloudProvider, String> {
     if data.project_name.trim().is_empty() {
         return Err("Project name is required".into());
     }
 
-    let valid_services = ["AWS", "Azure", "GCP"];
-    if !valid_services.contains(&data.selected_service.as_str()) {
-        return Err("Invalid cloud service selected".into());
+    let Some(provider) = find_provider(&data.selected_service) else {
+        return Err(format!("Unsupported cloud service '{}'", data.selected_service));
+    };
+
+    if !provider.regions().contains(&data.region.as_str()) {
+        return Err(format!("Region '{}' is not available on {}", data.region, provider.name()));
+    }
+
+    if provider.server_type(&data.selected_server).is_none() {
+        return Err(format!("Server type '{}' is not available on {}", data.selected_server, provider.name()));
     }
 
     if data.volume_size == 0 {
@@ -64,59 +106,152 @@ pub fn validate_request(data: &DeploymentRequest) -> Result<(), String> {
         return Err("SSH key is required when using 'existing'".into());
     }
 
-    Ok(())
-}
+    if let Some(ssh_key) = &data.ssh_key {
+        validate_public_key(ssh_key)?;
+    }
 
-pub async fn deploy(app_data: web::Data<AppConfig>, deploymentrequest: web::Json<DeploymentRequest>) -> impl Responder {
-    println!("📥 Received deploy request: {:?}", deploymentrequest);
+    Ok(provider)
+}
 
-    // Validate request fields
-    if let Err(err_msg) = validate_request(&deploymentrequest) {
-        println!("❌ Validation failed: {}", err_msg);
-        return HttpResponse::BadRequest().json(ApiResponse {
-            status: "error".into(),
-            message: err_msg,
-            returneddata: None,
-        });
+// The template the request names, or its provider's default
+pub fn request_template<'a>(provider: &dyn CloudProvider, deploymentrequest: &'a DeploymentRequest) -> &'a str {
+    match deploymentrequest.terraform_template.trim() {
+        "" => provider.default_template(),
+        template => template,
     }
+}
 
-    // Initialize MongoDB client early to check user existence
-    let mongo_client = init_mongo_client().await;
-
+// Looks up the requesting user and the credential the request uses, shared by deploy and plan
+pub async fn verify_deploy_user(mongo_client: Client, provider: &'static dyn CloudProvider, deploymentrequest: &DeploymentRequest) -> Result<(ObjectId, CredentialRef), HttpResponse> {
     // Check if user exists by email
-    match find_user_by_email(mongo_client.clone(), &deploymentrequest.user_email).await {
-        Ok(Some(_user_doc)) => {
+    let user_id = match find_user_by_email(mongo_client.clone(), &deploymentrequest.user_email).await {
+        Ok(Some(user_doc)) => {
             println!("✅ User found, proceeding with deployment");
+            match user_doc.get_object_id("_id") {
+                Ok(oid) => oid,
+                Err(_) => {
+                    return Err(HttpResponse::InternalServerError().json(ApiResponse {
+                        status: "error".into(),
+                        message: "Failed to extract user ID from user document".into(),
+                        returneddata: None,
+                    }));
+                }
+            }
         }
         Ok(None) => {
             println!("❌ User with email '{}' not found", deploymentrequest.user_email);
-            return HttpResponse::BadRequest().json(ApiResponse {
+            return Err(HttpResponse::BadRequest().json(ApiResponse {
                 status: "error".into(),
                 message: format!("User with email '{}' not found", deploymentrequest.user_email),
                 returneddata: None,
-            });
+            }));
         }
         Err(e) => {
             eprintln!("❌ Error querying user: {}", e);
-            return HttpResponse::InternalServerError().json(ApiResponse {
+            return Err(HttpResponse::InternalServerError().json(ApiResponse {
                 status: "error".into(),
                 message: "Internal server error while verifying user".into(),
                 returneddata: None,
-            });
+            }));
         }
-    }
+    };
 
-    // ✅ Fetch cloud provider key and store it
-    let cloud_provider = match fetch_cloud_provider(&deploymentrequest.user_email).await {
-        Ok(cloud_key) => {
+    // ✅ Find the cloud credential; it stays sealed until Terraform runs
+    match CredentialRef::resolve(mongo_client, &user_id, provider, deploymentrequest.credential_id.as_deref()).await {
+        Ok(credential) => {
             println!("✅ Cloud provider key found !");
-            cloud_key
+            Ok((user_id, credential))
         }
-        Err(e) => {
-            eprintln!("❌ Cloud provider error: {}", e);
+        Err(e) => Err(HttpResponse::BadRequest().json(ApiResponse {
+            status: "error".into(),
+            message: format!("{}. Cannot proceed with deployment.", e),
+            returneddata: None,
+        })),
+    }
+}
+
+// Values for the template's variables derived from this request and its app, plus any extras it carries
+pub fn build_variables(provider: &dyn CloudProvider, deploymentrequest: &DeploymentRequest, app_variables: serde_json::Map<String, serde_json::Value>) -> Result<serde_json::Map<String, serde_json::Value>, String> {
+    let server_type = provider
+        .server_type(&deploymentrequest.selected_server)
+        .ok_or_else(|| format!("Server type '{}' is not available on {}", deploymentrequest.selected_server, provider.name()))?;
+
+    let mut variables = app_variables;
+    variables.extend(provider.server_variables(deploymentrequest, server_type));
+
+    if let Some(ssh_key) = &deploymentrequest.ssh_key {
+        variables.insert(SSH_PUBLIC_KEY_VARIABLE.into(), json!(validate_public_key(ssh_key)?));
+    }
+
+    for (name, value) in &deploymentrequest.variables {
+        if variables.contains_key(name) {
+            return Err(format!("Variable '{}' is set from the request fields and cannot be overridden", name));
+        }
+        variables.insert(name.clone(), value.clone());
+    }
+
+    Ok(variables)
+}
+
+// Builds and checks the variables for a request against its template, shared by deploy and plan.
+// The app's cloud-init comes back separately: it carries app passwords, so it is handed to Terraform as a secret and never stored as a variable
+pub async fn resolve_variables(store: &dyn ArtifactStore, provider: &dyn CloudProvider, manifest: &TemplateManifest, deploymentrequest: &DeploymentRequest) -> Result<(serde_json::Map<String, serde_json::Value>, Option<String>), String> {
+    // A template for another cloud would get the wrong credentials and variables
+    if !manifest.provider.eq_ignore_ascii_case(provider.id()) {
+        return Err(format!("Template '{}' is for {}, not {}", manifest.name, manifest.provider, provider.name()));
+    }
+
+    let mut app_variables = app_variables(store, provider, manifest, deploymentrequest).await?;
+    let user_data = match app_variables.remove(USER_DATA_VARIABLE) {
+        Some(serde_json::Value::String(user_data)) => Some(user_data),
+        _ => None,
+    };
+
+    let variables = build_variables(provider, deploymentrequest, app_variables)?;
+    let variables = validate_variables(manifest, &variables, &secret_variable_names(provider, user_data.is_some()))?;
+    Ok((variables, user_data))
+}
+
+pub async fn deploy(store: web::Data<dyn ArtifactStore>, keyring: web::Data<Keyring>, job_queue: web::Data<JobQueue>, deploymentrequest: web::Json<DeploymentRequest>) -> impl Responder {
+    println!("📥 Received deploy request: {:?}", deploymentrequest);
+    let mut deploymentrequest = deploymentrequest.into_inner();
+
+    // Validate request fields
+    let provider = match validate_request(&deploymentrequest) {
+        Ok(provider) => provider,
+        Err(err_msg) => {
+            println!("❌ Validation failed: {}", err_msg);
             return HttpResponse::BadRequest().json(ApiResponse {
                 status: "error".into(),
-                message: "Cloud provider not set for this user. Cannot proceed with deployment.".into(),
+                message: err_msg,
+                returneddata: None,
+            });
+        }
+    };
+
+    // Initialize MongoDB client early to check user existence
+    let mongo_client = init_mongo_client().await;
+
+    let (user_id, credential) = match verify_deploy_user(mongo_client.clone(), provider, &deploymentrequest).await {
+        Ok(found) => found,
+        Err(resp) => return resp,
+    };
+    // Recorded with the deployment so later runs use the same account even if the user's default changes
+    deploymentrequest.credential_id = Some(credential.credential_id.clone());
+
+    // Unknown templates would otherwise copy nothing and run Terraform on an empty folder
+    let (template, manifest) = match resolve_template_version(mongo_client.clone(), store.get_ref(), request_template(provider, &deploymentrequest), deploymentrequest.template_version.as_deref()).await {
+        Ok(resolved) => resolved,
+        Err(resp) => return resp,
+    };
+
+    let (variables, user_data) = match resolve_variables(store.get_ref(), provider, &manifest, &deploymentrequest).await {
+        Ok(resolved) => resolved,
+        Err(err_msg) => {
+            println!("❌ Variable validation failed: {}", err_msg);
+            return HttpResponse::BadRequest().json(ApiResponse {
+                status: "error".into(),
+                message: err_msg,
                 returneddata: None,
             });
         }
@@ -127,57 +262,322 @@ pub async fn deploy(app_data: web::Data<AppConfig>, deploymentrequest: web::Json
     println!("--------------------------------------------------------");
 
     let project_id = Uuid::new_v4().to_string();
-    let bucket = &app_data.s3_bucket;
-    let source_prefix = format!("terraform/{}/", deploymentrequest.terraform_template);
-    let destination_prefix = format!("deployments/{} (project_id: {})/", deploymentrequest.project_name, project_id);
-
-    let mut replacements = HashMap::new();
-    replacements.insert("__NODE_NAME__".to_string(), format!("{}-server", deploymentrequest.project_name));
-    replacements.insert("__SERVER_TYPE__".to_string(), deploymentrequest.selected_server.clone());
-    replacements.insert("__LOCATION__".to_string(), deploymentrequest.region.clone());
-    replacements.insert("__HCLOUD_TOKEN__".to_string(), cloud_provider.clone());
+    let job_id = Uuid::new_v4().to_string();
 
+    // Sealed straight away, so the app's passwords are never stored or queued in plaintext
+    let user_data = match user_data.map(|user_data| seal_user_data(&keyring, &project_id, &user_data)).transpose() {
+        Ok(user_data) => user_data,
+        Err(e) => {
+            eprintln!("❌ Failed to seal user_data: {}", e);
+            return HttpResponse::InternalServerError().json(ApiResponse {
+                status: "error".into(),
+                message: "Failed to secure the app configuration".into(),
+                returneddata: None,
+            });
+        }
+    };
 
-    let aws_config = aws_config::load_defaults(BehaviorVersion::latest()).await;
-    let s3_client = aws_sdk_s3::Client::new(&aws_config);
+    // Persist the job before queueing it so a poll never races the worker
+    if let Err(e) = create_job(mongo_client.clone(), &job_id, &project_id, "deploy", &deploymentrequest.user_email).await {
+        eprintln!("❌ Failed to create deployment job: {}", e);
+        return HttpResponse::InternalServerError().json(ApiResponse {
+            status: "error".into(),
+            message: "Failed to create deployment job".into(),
+            returneddata: None,
+        });
+    }
 
-    // Copy and transform Terraform files in S3
-    if let Err(e) = copy_and_transform_files(&s3_client, &bucket, &source_prefix, &destination_prefix, &replacements, None).await {
-        eprintln!("❌ S3 copy error: {}", e);
+    // The deployment record exists from the moment it is queued so every later transition has something to update
+    let stored = match store_deployment_metadata(mongo_client.clone(), &deploymentrequest, provider.id(), &project_id, &user_id, &template, &variables, &job_id).await {
+        Ok(()) => match &user_data {
+            // Later reapplies need the same cloud-init, or the server would be replaced
+            Some(sealed) => store_sealed_user_data(&mongo_client, &project_id, sealed).await,
+            None => Ok(()),
+        },
+        Err(e) => Err(e),
+    };
+    if let Err(e) = stored {
+        eprintln!("❌ Failed to save deployment metadata: {}", e);
+        if let Err(e) = update_job_status(mongo_client.clone(), &job_id, "failed", "Failed to save metadata", None).await {
+            eprintln!("❌ Failed to mark job {} as failed: {}", job_id, e);
+        }
         return HttpResponse::InternalServerError().json(ApiResponse {
             status: "error".into(),
-            message: "Failed to copy and modify templates from S3".into(),
+            message: "Failed to save metadata".into(),
             returneddata: None,
         });
     }
 
-    println!("✅ S3 templates copied to: {}", destination_prefix);
-    println!("--------------------------------------------------------");
+    let job = DeployJob {
+        job_id: job_id.clone(),
+        project_id: project_id.clone(),
+        user_id,
+        request: deploymentrequest,
+        credential,
+        template,
+        variables,
+        user_data,
+    };
 
-    // Now call execute_deployment to download, apply terraform etc.
-    if let Err(e) = execute_deployment(&s3_client, bucket, &destination_prefix).await {
-        eprintln!("❌ Deployment execution error: {}", e);
-        return HttpResponse::InternalServerError().json(ApiResponse {
+    if let Err(e) = job_queue.enqueue(Job::Deploy(Box::new(job))) {
+        eprintln!("❌ Failed to queue deployment job {}: {}", job_id, e);
+        if let Err(e) = update_job_status(mongo_client.clone(), &job_id, "failed", &e, None).await {
+            eprintln!("❌ Failed to mark job {} as failed: {}", job_id, e);
+        }
+        fail_deployment(mongo_client.clone(), &project_id, &e).await;
+        return HttpResponse::ServiceUnavailable().json(ApiResponse {
             status: "error".into(),
-            message: format!("Failed during deployment execution: {}", e),
+            message: e,
             returneddata: None,
         });
     }
 
-    let deployment_data = deploymentrequest.into_inner();
+    println!("📬 Queued deployment job {} (project_id: {})", job_id, project_id);
+    println!("--------------------------------------------------------");
 
-    if let Err(resp) = store_deployment_metadata(mongo_client.clone(), &deployment_data, &project_id).await {
-        return resp;
+    HttpResponse::Accepted().json(ApiResponse {
+        status: "accepted".into(),
+        message: "Deployment queued".into(),
+        returneddata: Some(json!({
+            "job_id": job_id,
+            "project_id": project_id,
+        })),
+    })
+}
+
+// Runs on a deployment worker: renders the templates, applies them and moves the deployment through its states
+pub async fn run_deploy_job(app_config: &AppConfig, mongo_client: Client, store: &dyn ArtifactStore, keyring: &Keyring, log_hub: &LogHub, job: DeployJob) -> Result<Vec<String>, JobFailure> {
+    // The deployment was inserted holding a lease for this job, so nothing else can have started on it
+    let lock = match OperationLock::adopt(mongo_client.clone(), &job.project_id, &job.job_id, "deploy").await {
+        Ok(lock) => lock,
+        Err(e) => {
+            eprintln!("❌ Cannot deploy {}: {}", job.project_id, e);
+            let reason = format!("Could not lock deployment: {}", e);
+            fail_deployment(mongo_client, &job.project_id, &reason).await;
+            return Err(reason.into());
+        }
+    };
+
+    let result = provision_deployment(app_config, mongo_client, store, keyring, log_hub, &job).await;
+    lock.release().await;
+    result
+}
+
+pub fn deployment_prefix(project_name: &str, project_id: &str) -> String {
+    format!("deployments/{} (project_id: {})/", project_name, project_id)
+}
+
+// Writes everything Terraform needs for a deployment into its folder in the store: the pinned template, the backend and the variables.
+// Returns the declared placeholders that no templated file used
+pub async fn render_deployment(store: &dyn ArtifactStore, template: &TemplateVersion, destination_prefix: &str, project_id: &str, variables: &serde_json::Map<String, serde_json::Value>) -> Result<Vec<String>, String> {
+    let source_prefix = template_version_prefix(&template.name, &template.version);
+
+    if let Err(e) = verify_template_version(store, template).await {
+        eprintln!("❌ Template integrity error: {}", e);
+        return Err(e.to_string());
     }
 
-    HttpResponse::Ok().json(ApiResponse {
-        status: "success".into(),
-        message: "✅ Deployment initialized and executed".into(),
-        returneddata: None,
+    // The manifest was hashed with the rest of the version, so its templated_files are the ones that were published
+    let manifest = match read_manifest(store, &source_prefix, &template.name).await {
+        Ok(Some(manifest)) => manifest,
+        Ok(None) => return Err(format!("Template {} version {} has no manifest", template.name, template.version)),
+        Err(e) => {
+            eprintln!("❌ Template manifest error: {}", e);
+            return Err(e.to_string());
+        }
+    };
+
+    // Copy and transform Terraform files in the store
+    let replacements = placeholder_replacements(&manifest, variables);
+    let unused_placeholders = match copy_and_transform_files(store, &source_prefix, destination_prefix, &manifest.templated_files, &replacements).await {
+        Ok(unused) => unused,
+        Err(e) => {
+            eprintln!("❌ Template copy error: {}", e);
+            return Err(format!("Failed to render templates: {}", e));
+        }
+    };
+
+    println!("✅ Templates copied to: {}", destination_prefix);
+    println!("--------------------------------------------------------");
+
+    // Point Terraform at the shared state backend before the first init
+    if let Err(e) = upload_backend_config(store, destination_prefix, project_id).await {
+        eprintln!("❌ Backend config error: {}", e);
+        return Err("Failed to write the Terraform state backend configuration".to_string());
+    }
+
+    // Values go in a JSON tfvars file so nothing from the request is ever spliced into HCL
+    if let Err(e) = upload_tfvars(store, destination_prefix, variables).await {
+        eprintln!("❌ Terraform variables error: {}", e);
+        return Err("Failed to write the Terraform variables".to_string());
+    }
+
+    Ok(unused_placeholders)
+}
+
+// Returns the declared placeholders the template never used
+async fn provision_deployment(app_config: &AppConfig, mongo_client: Client, store: &dyn ArtifactStore, keyring: &Keyring, log_hub: &LogHub, job: &DeployJob) -> Result<Vec<String>, JobFailure> {
+    let deploymentrequest = &job.request;
+    let project_id = &job.project_id;
+    let destination_prefix = deployment_prefix(&deploymentrequest.project_name, project_id);
+
+    advance_deployment(mongo_client.clone(), project_id, DeploymentState::CopyingTemplates, "Copying Terraform templates").await?;
+
+    let unused_placeholders = match render_deployment(store, &job.template, &destination_prefix, project_id, &job.variables).await {
+        Ok(unused) => unused,
+        Err(reason) => {
+            fail_deployment(mongo_client, project_id, &reason).await;
+            return Err(reason.into());
+        }
+    };
+
+    // Opened only now, so the plaintext credential and cloud-init never sit in the queue
+    let secrets = match job.credential.secrets(mongo_client.clone(), keyring).await.and_then(|mut secrets| {
+        add_user_data(&mut secrets, keyring, project_id, job.user_data.as_ref())?;
+        Ok(secrets)
+    }) {
+        Ok(secrets) => secrets,
+        Err(reason) => {
+            fail_deployment(mongo_client, project_id, &reason).await;
+            return Err(reason.into());
+        }
+    };
+
+    advance_deployment(mongo_client.clone(), project_id, DeploymentState::Provisioning, "Running Terraform").await?;
+
+    // Now call execute_deployment to download, apply terraform etc.
+    let log = log_hub.channel(project_id);
+    let mut output = RunLog::new(&log);
+    let run = start_terraform_run(mongo_client.clone(), project_id, "terraform init && terraform plan -out=tfplan && terraform apply tfplan").await;
+
+    let result = execute_deployment(store, &destination_prefix, project_id, &secrets, None, &mut output).await;
+
+    match &run {
+        Ok(run) => {
+            let error = result.as_ref().err().map(|e| e.to_string());
+            finish_terraform_run(mongo_client.clone(), store, run, &output, error).await;
+        }
+        Err(e) => eprintln!("⚠️ Failed to create run record for {}: {}", project_id, e),
+    }
+    drop(output);
+
+    let outputs = match result {
+        Ok(outputs) => outputs,
+        Err(e) => {
+            eprintln!("❌ Deployment execution error: {}", e);
+
+            // Apply may have stopped halfway, so tear down whatever it created before giving up
+            let rollback = rollback_deployment(app_config, mongo_client.clone(), store, &log, project_id, &destination_prefix, &secrets).await;
+            log.finish();
+
+            if let Err(e) = store_rollback_outcome(mongo_client.clone(), project_id, &rollback).await {
+                eprintln!("⚠️ Failed to store rollback outcome for {}: {}", project_id, e);
+            }
+
+            let reason = format!("Failed during deployment execution: {} (rollback: {})", e, rollback["outcome"].as_str().unwrap_or("unknown"));
+            fail_deployment(mongo_client, project_id, &reason).await;
+
+            let mut details = terraform_error_details(e.as_ref()).unwrap_or_else(|| json!({}));
+            if let Some(details) = details.as_object_mut() {
+                details.insert("rollback".into(), rollback);
+            }
+            return Err(JobFailure {
+                message: reason,
+                details: Some(details),
+            });
+        }
+    };
+    log.finish();
+
+    if let Err(e) = store_deployment_outputs(mongo_client.clone(), project_id, &outputs).await {
+        eprintln!("⚠️ Failed to store outputs for {}: {}", project_id, e);
+    }
+
+    advance_deployment(mongo_client, project_id, DeploymentState::Running, "Terraform apply completed").await?;
+
+    Ok(unused_placeholders)
+}
+
+// Destroys what a failed apply left behind according to the configured policy and reports what happened
+async fn rollback_deployment(app_config: &AppConfig, mongo_client: Client, store: &dyn ArtifactStore, log: &LogChannel, project_id: &str, prefix: &str, secrets: &TerraformSecrets) -> serde_json::Value {
+    let policy = app_config.rollback_policy;
+
+    if policy == RollbackPolicy::Keep {
+        println!("⏸️ Rollback policy is 'keep', leaving partial infrastructure for {} in place", project_id);
+        println!("--------------------------------------------------------");
+        return json!({
+            "policy": policy.as_str(),
+            "outcome": "skipped",
+            "error": null,
+            "timestamp": Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        });
+    }
+
+    println!("↩️ Rolling back partially provisioned deployment {}", project_id);
+    println!("--------------------------------------------------------");
+
+    let mut output = RunLog::new(log);
+    output.note("↩️ Apply failed, destroying partially created resources...");
+    let run = start_terraform_run(mongo_client.clone(), project_id, "terraform destroy -auto-approve (rollback)").await;
+
+    // The state lives in the backend, so destroy sees everything apply managed to create
+    let destroy_result = destroy_terraform_resources(store, prefix, project_id, secrets, &mut output).await;
+
+    match &run {
+        Ok(run) => {
+            let error = destroy_result.as_ref().err().map(|e| e.to_string());
+            finish_terraform_run(mongo_client.clone(), store, run, &output, error).await;
+        }
+        Err(e) => eprintln!("⚠️ Failed to create run record for {}: {}", project_id, e),
+    }
+
+    let (outcome, error) = match destroy_result {
+        Ok(()) => {
+            // Nothing is left to manage, so the rendered templates and the state can go too
+            let mut cleanup_error = None;
+            for cleanup_prefix in [prefix.to_string(), state_prefix(project_id)] {
+                if let Err(e) = delete_specific_deployment_folder(store, &cleanup_prefix).await {
+                    eprintln!("⚠️ Failed to delete {} after rollback: {}", cleanup_prefix, e);
+                    cleanup_error = Some(format!("Resources destroyed but {} could not be deleted: {}", cleanup_prefix, e));
+                }
+            }
+            println!("✅ Rollback of {} completed", project_id);
+            println!("--------------------------------------------------------");
+            ("destroyed", cleanup_error)
+        }
+        Err(e) => {
+            // The templates stay in the store so the deployment can still be undeployed by hand
+            eprintln!("❌ Rollback destroy failed for {}: {}", project_id, e);
+            ("failed", Some(e.to_string()))
+        }
+    };
+
+    json!({
+        "policy": policy.as_str(),
+        "outcome": outcome,
+        "error": error,
+        "timestamp": Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
     })
 }
 
-pub async fn undeploy(app_data: web::Data<AppConfig>,request: web::Json<UndeployRequest>) -> impl Responder {
+pub async fn fail_deployment(mongo_client: Client, project_id: &str, reason: &str) {
+    if let Err(e) = transition_deployment_state(mongo_client, project_id, DeploymentState::Failed, reason).await {
+        eprintln!("❌ Failed to mark deployment {} as failed: {}", project_id, e);
+    }
+}
+
+// Moves a job's deployment on; if the move is refused the deployment is marked failed rather than left where it was
+pub async fn advance_deployment(mongo_client: Client, project_id: &str, to: DeploymentState, reason: &str) -> Result<(), JobFailure> {
+    if let Err(e) = transition_deployment_state(mongo_client.clone(), project_id, to, reason).await {
+        eprintln!("❌ Cannot move deployment {} to '{}': {}", project_id, to, e);
+        let reason = format!("Could not move deployment to '{}': {}", to, e);
+        fail_deployment(mongo_client, project_id, &reason).await;
+        return Err(reason.into());
+    }
+    Ok(())
+}
+
+pub async fn undeploy(store: web::Data<dyn ArtifactStore>,keyring: web::Data<Keyring>,log_hub: web::Data<LogHub>,request: web::Json<UndeployRequest>) -> impl Responder {
     
     println!("📥 Received undeploy request: {:?}", request);
     println!("--------------------------------------------");
@@ -226,62 +626,193 @@ pub async fn undeploy(app_data: web::Data<AppConfig>,request: web::Json<Undeploy
             });
         }
     };
-    let user_id_str = user_id.to_hex();
 
-    // Build the S3 prefix of the deployment to destroy and delete
-    let bucket = &app_data.s3_bucket;
-    let prefix_to_delete = format!(
-        "deployments/{} (project_id: {})/",
-        request.project_name, request.project_id
-    );
+    // Make sure the deployment belongs to this user before touching any infrastructure
+    let deployment = match find_deployment(mongo_client.clone(), &user_id, &request.project_id).await {
+        Ok(Some(deployment)) => deployment,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(ApiResponse {
+                status: "error".into(),
+                message: "No matching deployment found for the given project_id".into(),
+                returneddata: None,
+            });
+        }
+        Err(e) => {
+            eprintln!("❌ Error finding deployment: {}", e);
+            return HttpResponse::InternalServerError().json(ApiResponse {
+                status: "error".into(),
+                message: "Internal server error while looking up deployment".into(),
+                returneddata: None,
+            });
+        }
+    };
+
+    // Only one Terraform operation may touch a deployment at a time
+    let lock = match OperationLock::acquire(mongo_client.clone(), &request.project_id, "undeploy").await {
+        Ok(lock) => lock,
+        Err(e) => {
+            eprintln!("❌ Cannot undeploy {}: {}", request.project_id, e);
+            return match e {
+                LockError::Held { .. } => HttpResponse::Conflict().json(ApiResponse {
+                    status: "error".into(),
+                    message: e.to_string(),
+                    returneddata: None,
+                }),
+                LockError::NotFound => HttpResponse::NotFound().json(ApiResponse {
+                    status: "error".into(),
+                    message: "No matching deployment found for the given project_id".into(),
+                    returneddata: None,
+                }),
+                LockError::Database(_) => HttpResponse::InternalServerError().json(ApiResponse {
+                    status: "error".into(),
+                    message: "Failed to lock deployment".into(),
+                    returneddata: None,
+                }),
+            };
+        }
+    };
+
+    // Destroy runs on its own task, so a client that disconnects cannot cancel it halfway and leave Terraform running unlocked
+    let request = request.into_inner();
+    let project_id = request.project_id.clone();
+    let destroy = actix_web::rt::spawn(async move {
+        let response = destroy_deployment(store.get_ref(), &keyring, &deployment, &log_hub, mongo_client, &request).await;
+        lock.release().await;
+        response
+    });
+
+    match destroy.await {
+        Ok(response) => response,
+        Err(e) => {
+            eprintln!("❌ Undeploy of {} panicked: {}", project_id, e);
+            fail_deployment(init_mongo_client().await, &project_id, "Undeploy was interrupted").await;
+            HttpResponse::InternalServerError().json(ApiResponse {
+                status: "error".into(),
+                message: "Undeploy was interrupted".into(),
+                returneddata: None,
+            })
+        }
+    }
+}
+
+async fn destroy_deployment(store: &dyn ArtifactStore, keyring: &Keyring, deployment: &Document, log_hub: &LogHub, mongo_client: Client, request: &UndeployRequest) -> HttpResponse {
+    // Destroy has to run against the account the deployment was created in
+    let secrets = match CredentialRef::for_deployment(mongo_client.clone(), deployment).await {
+        Ok(credential) => credential.secrets(mongo_client.clone(), keyring).await,
+        Err(e) => Err(e),
+    };
+    let secrets = match secrets {
+        Ok(secrets) => secrets,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(ApiResponse {
+                status: "error".into(),
+                message: format!("{}. Cannot destroy the deployment.", e),
+                returneddata: None,
+            });
+        }
+    };
 
-    // Initialize AWS S3 client
-    let aws_config = aws_config::load_defaults(BehaviorVersion::latest()).await;
-    let s3_client = aws_sdk_s3::Client::new(&aws_config);
+    if let Err(e) = transition_deployment_state(mongo_client.clone(), &request.project_id, DeploymentState::Destroying, "Undeploy requested").await {
+        eprintln!("❌ Cannot undeploy {}: {}", request.project_id, e);
+        return match e {
+            TransitionError::Illegal { .. } => HttpResponse::Conflict().json(ApiResponse {
+                status: "error".into(),
+                message: e.to_string(),
+                returneddata: None,
+            }),
+            TransitionError::NotFound => HttpResponse::NotFound().json(ApiResponse {
+                status: "error".into(),
+                message: "No matching deployment found for the given project_id".into(),
+                returneddata: None,
+            }),
+            TransitionError::Database(_) => HttpResponse::InternalServerError().json(ApiResponse {
+                status: "error".into(),
+                message: "Failed to update deployment status".into(),
+                returneddata: None,
+            }),
+        };
+    }
+
+    // The stored name, not the request's: a wrong name would point destroy at an empty folder
+    let prefix_to_delete = deployment_prefix(deployment.get_str("project_name").unwrap_or_default(), &request.project_id);
 
     // Step 1: Destroy Terraform resources
-    match destroy_terraform_resources(&s3_client, bucket, &prefix_to_delete).await {
+    let log = log_hub.channel(&request.project_id);
+    let mut output = RunLog::new(&log);
+    let run = start_terraform_run(mongo_client.clone(), &request.project_id, "terraform destroy -auto-approve").await;
+
+    let destroy_result = destroy_terraform_resources(store, &prefix_to_delete, &request.project_id, &secrets, &mut output).await;
+
+    match &run {
+        Ok(run) => {
+            let error = destroy_result.as_ref().err().map(|e| e.to_string());
+            finish_terraform_run(mongo_client.clone(), store, run, &output, error).await;
+        }
+        Err(e) => eprintln!("⚠️ Failed to create run record for {}: {}", request.project_id, e),
+    }
+    drop(output);
+    log.finish();
+
+    match destroy_result {
         Ok(_) => {
             println!("✅ Terraform destroy completed successfully.");
             println!("--------------------------------------------");
         }
         Err(e) => {
             eprintln!("❌ Terraform destroy failed: {}", e);
+            let reason = format!("Failed to destroy Terraform resources: {}", e);
+            fail_deployment(mongo_client.clone(), &request.project_id, &reason).await;
             return HttpResponse::InternalServerError().json(ApiResponse {
                 status: "error".into(),
-                message: format!("Failed to destroy Terraform resources: {}", e),
-                returneddata: None,
+                message: reason,
+                returneddata: terraform_error_details(e.as_ref()),
             });
         }
     }
 
-    // Step 2: Delete deployment folder from S3
-    match delete_specific_deployment_folder(&s3_client, bucket, &prefix_to_delete).await {
+    // Step 2: Delete deployment folder from the store
+    let cleanup_error = match delete_specific_deployment_folder(store, &prefix_to_delete).await {
         Ok(true) => {
-            println!("✅ Deployment folder deleted from S3.");
+            println!("✅ Deployment folder deleted from the store.");
             println!("--------------------------------------------");
-
-            // Step 3: Delete deployment metadata from MongoDB
-            match delete_deployment(mongo_client.clone(), &user_id_str, &request.project_id).await {
-                Ok(resp) => resp,
-                Err(err_resp) => err_resp,
-            }
+            None
         }
         Ok(false) => {
-            println!("⚠ No files found in S3 to delete.");
-            HttpResponse::NotFound().json(ApiResponse {
-                status: "error".into(),
-                message: "No files found in S3 for the given project_id".into(),
-                returneddata: None,
-            })
+            println!("⚠ No deployment files found to delete.");
+            None
         }
         Err(e) => {
-            eprintln!("❌ S3 deletion error: {}", e);
-            HttpResponse::InternalServerError().json(ApiResponse {
-                status: "error".into(),
-                message: format!("Failed to delete deployment files from S3: {}", e),
-                returneddata: None,
-            })
+            eprintln!("❌ Storage deletion error: {}", e);
+            Some(format!("Failed to delete deployment files: {}", e))
         }
+    };
+
+    // The state object outlives the destroy; it is empty now and only takes up space.
+    // Only reached when destroy ran against this deployment's own backend
+    if let Err(e) = delete_specific_deployment_folder(store, &state_prefix(&request.project_id)).await {
+        eprintln!("⚠️ Failed to delete remote state for {}: {}", request.project_id, e);
+    }
+
+    // Step 3: The infrastructure is gone either way, so record it as destroyed
+    if let Err(e) = transition_deployment_state(mongo_client.clone(), &request.project_id, DeploymentState::Destroyed, "Terraform destroy completed").await {
+        eprintln!("❌ Failed to mark deployment {} as destroyed: {}", request.project_id, e);
+        return HttpResponse::InternalServerError().json(ApiResponse {
+            status: "error".into(),
+            message: "Resources were destroyed but the deployment status could not be updated".into(),
+            returneddata: None,
+        });
+    }
+
+    match cleanup_error {
+        None => HttpResponse::Ok().json(ApiResponse {
+            status: "success".to_string(),
+            message: format!("Deployment with project_id '{}' destroyed successfully.", request.project_id),
+            returneddata: None,
+        }),
+        Some(message) => HttpResponse::InternalServerError().json(ApiResponse {
+            status: "error".into(),
+            message,
+            returneddata: None,
+        }),
     }
 }
\ No newline at end of file
@@ -0,0 +1,58 @@
+use actix_web::{web, HttpResponse, Responder};
+use futures::stream;
+use serde::Deserialize;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::{
+    utils::deployment::upgrade::find_user_deployment,
+    utils::terraform::log_stream::{LogEvent, LogHub},
+    utils::user::signup_func::init_mongo_client,
+};
+
+const END_FRAME: &str = "event: end\ndata: done\n\n";
+
+#[derive(Deserialize)]
+pub struct LogsQuery {
+    pub user_email: String,
+}
+
+fn event_stream() -> actix_web::HttpResponseBuilder {
+    let mut response = HttpResponse::Ok();
+    response
+        .insert_header(("Content-Type", "text/event-stream"))
+        .insert_header(("Cache-Control", "no-cache"));
+    response
+}
+
+// Server-Sent Events stream of the Terraform output for a deployment's current operation
+pub async fn stream_deployment_logs(log_hub: web::Data<LogHub>, path: web::Path<String>, query: web::Query<LogsQuery>) -> impl Responder {
+    let project_id = path.into_inner();
+
+    let mongo_client = init_mongo_client().await;
+    if let Err(resp) = find_user_deployment(mongo_client, &query.user_email, &project_id).await {
+        return resp;
+    }
+
+    // Nothing is running, so end right away rather than leave the client waiting for an operation that may never start
+    let Some(receiver) = log_hub.subscribe(&project_id) else {
+        return event_stream().body(END_FRAME);
+    };
+
+    println!("📡 Client subscribed to logs for project_id: {}", project_id);
+    println!("--------------------------------------------------------");
+
+    let events = stream::unfold(Some(receiver), |receiver| async move {
+        let mut receiver = receiver?;
+        let frame = match receiver.recv().await {
+            Ok(LogEvent::Line(line)) => format!("data: {}\n\n", line),
+            // A closed channel means the operation went away without finishing; the client should stop either way
+            Ok(LogEvent::End) | Err(RecvError::Closed) => {
+                return Some((Ok::<_, actix_web::Error>(web::Bytes::from(END_FRAME)), None));
+            }
+            Err(RecvError::Lagged(skipped)) => format!(": skipped {} lines\n\n", skipped),
+        };
+        Some((Ok(web::Bytes::from(frame)), Some(receiver)))
+    });
+
+    event_stream().streaming(events)
+}
@@ -0,0 +1,101 @@
+use actix_web::{web, HttpResponse, Responder};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    deploy::ApiResponse,
+    utils::database::db::{find_terraform_run, list_terraform_runs},
+    utils::deployment::upgrade::find_user_deployment,
+    utils::storage::artifact_store::ArtifactStore,
+    utils::user::signup_func::init_mongo_client,
+};
+
+#[derive(Deserialize)]
+pub struct RunsQuery {
+    pub user_email: String,
+}
+
+pub async fn list_deployment_runs(path: web::Path<String>, query: web::Query<RunsQuery>) -> impl Responder {
+    let project_id = path.into_inner();
+    let mongo_client = init_mongo_client().await;
+
+    // Run logs can hold anything Terraform printed, so only the deployment's owner may read them
+    if let Err(resp) = find_user_deployment(mongo_client.clone(), &query.user_email, &project_id).await {
+        return resp;
+    }
+
+    match list_terraform_runs(mongo_client, &project_id).await {
+        Ok(runs) => HttpResponse::Ok().json(ApiResponse {
+            status: "success".into(),
+            message: if runs.is_empty() {
+                "No Terraform runs found".into()
+            } else {
+                "Terraform runs fetched successfully".into()
+            },
+            returneddata: Some(json!({ "runs": runs })),
+        }),
+        Err(e) => {
+            eprintln!("❌ Error listing runs for {}: {}", project_id, e);
+            HttpResponse::InternalServerError().json(ApiResponse {
+                status: "error".into(),
+                message: "Failed to fetch Terraform runs".into(),
+                returneddata: None,
+            })
+        }
+    }
+}
+
+pub async fn get_deployment_run_log(store: web::Data<dyn ArtifactStore>, path: web::Path<(String, String)>, query: web::Query<RunsQuery>) -> impl Responder {
+    let (project_id, run_id) = path.into_inner();
+    let mongo_client = init_mongo_client().await;
+
+    if let Err(resp) = find_user_deployment(mongo_client.clone(), &query.user_email, &project_id).await {
+        return resp;
+    }
+
+    let run = match find_terraform_run(mongo_client, &project_id, &run_id).await {
+        Ok(Some(run)) => run,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(ApiResponse {
+                status: "error".into(),
+                message: format!("Run '{}' not found for this deployment", run_id),
+                returneddata: None,
+            });
+        }
+        Err(e) => {
+            eprintln!("❌ Error fetching run {}: {}", run_id, e);
+            return HttpResponse::InternalServerError().json(ApiResponse {
+                status: "error".into(),
+                message: "Failed to fetch Terraform run".into(),
+                returneddata: None,
+            });
+        }
+    };
+
+    let Ok(log_key) = run.get_str("log_key") else {
+        return HttpResponse::InternalServerError().json(ApiResponse {
+            status: "error".into(),
+            message: "Run record has no log attached".into(),
+            returneddata: None,
+        });
+    };
+
+    match store.get(log_key).await {
+        Ok(Some(contents)) => HttpResponse::Ok()
+            .content_type("text/plain; charset=utf-8")
+            .body(contents),
+        Ok(None) => HttpResponse::NotFound().json(ApiResponse {
+            status: "error".into(),
+            message: "Log for this run is not available (it may still be running)".into(),
+            returneddata: None,
+        }),
+        Err(e) => {
+            eprintln!("❌ Failed to read log {}: {}", log_key, e);
+            HttpResponse::InternalServerError().json(ApiResponse {
+                status: "error".into(),
+                message: "Failed to read run log".into(),
+                returneddata: None,
+            })
+        }
+    }
+}
@@ -0,0 +1,301 @@
+use std::net::IpAddr;
+use actix_web::{web, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use uuid::Uuid;
+
+use crate::{
+    deploy::ApiResponse,
+    utils::apps::catalog::AppDefinition,
+    utils::database::db::{create_job, is_duplicate_key, update_job_status},
+    utils::deployment::cloud_init::{deployment_user_data, secret_variable_names},
+    utils::deployment::upgrade::{deployment_template, deployment_variables, error_response, find_running_deployment, find_user_deployment},
+    utils::jobs::job_queue::{Job, JobQueue, ReapplyJob},
+    utils::providers::cloud_provider::{deployment_provider, CloudProvider},
+    utils::settings::credentials::CredentialRef,
+    utils::storage::artifact_store::ArtifactStore,
+    utils::templates::catalog::read_manifest,
+    utils::templates::versions::template_version_prefix,
+    utils::terraform::variables::validate_variables,
+    utils::user::signup_func::init_mongo_client,
+};
+
+// Template variable holding the rules; templates that manage a firewall declare it
+pub const FIREWALL_RULES_VARIABLE: &str = "firewall_rules";
+
+const ANY_SOURCE: [&str; 2] = ["0.0.0.0/0", "::/0"];
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct FirewallRule {
+    #[serde(default = "default_direction")]
+    pub direction: String,
+    pub protocol: String,
+    // A single port or a range like "8000-8100"; only for tcp and udp
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<String>,
+    #[serde(default = "any_source")]
+    pub source_ips: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+// Identifies the rules a change removes; every inbound rule for the protocol and port goes
+#[derive(Deserialize, Debug)]
+pub struct FirewallRuleMatch {
+    pub protocol: String,
+    #[serde(default)]
+    pub port: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct FirewallQuery {
+    pub user_email: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct FirewallUpdateRequest {
+    pub user_email: String,
+    #[serde(default)]
+    pub remove: Vec<FirewallRuleMatch>,
+    #[serde(default)]
+    pub add: Vec<FirewallRule>,
+}
+
+fn default_direction() -> String {
+    "in".into()
+}
+
+fn any_source() -> Vec<String> {
+    ANY_SOURCE.iter().map(|ip| ip.to_string()).collect()
+}
+
+fn is_valid_port(port: &str) -> bool {
+    let parse = |p: &str| p.parse::<u16>().ok().filter(|p| *p > 0);
+    match port.split_once('-') {
+        Some((start, end)) => matches!((parse(start), parse(end)), (Some(start), Some(end)) if start <= end),
+        None => parse(port).is_some(),
+    }
+}
+
+fn is_valid_source(source: &str) -> bool {
+    let (ip, prefix) = source.split_once('/').unwrap_or((source, ""));
+    let Ok(ip) = ip.parse::<IpAddr>() else {
+        return false;
+    };
+    if prefix.is_empty() {
+        return true;
+    }
+    let max_prefix = if ip.is_ipv4() { 32 } else { 128 };
+    prefix.parse::<u8>().map(|prefix| prefix <= max_prefix).unwrap_or(false)
+}
+
+impl FirewallRule {
+    fn validate(&self, provider: &dyn CloudProvider) -> Result<(), String> {
+        if self.direction != "in" {
+            return Err(format!("Unsupported rule direction '{}', only 'in' rules can be managed", self.direction));
+        }
+
+        match self.protocol.as_str() {
+            "tcp" | "udp" => match self.port.as_deref() {
+                Some(port) if is_valid_port(port) => {}
+                Some(port) => return Err(format!("Invalid port '{}'", port)),
+                None => return Err(format!("A {} rule needs a port", self.protocol)),
+            },
+            "icmp" | "gre" | "esp" => {
+                if self.port.is_some() {
+                    return Err(format!("A {} rule cannot have a port", self.protocol));
+                }
+            }
+            other => return Err(format!("Unknown protocol '{}'", other)),
+        }
+
+        // Caught here rather than in the queued apply, which would fail a healthy deployment
+        if !provider.firewall_protocols().contains(&self.protocol.as_str()) {
+            return Err(format!("{} firewalls do not support {} rules", provider.name(), self.protocol));
+        }
+
+        if self.source_ips.is_empty() {
+            return Err("A rule needs at least one source IP".into());
+        }
+        if let Some(source) = self.source_ips.iter().find(|source| !is_valid_source(source)) {
+            return Err(format!("Invalid source IP or CIDR '{}'", source));
+        }
+
+        Ok(())
+    }
+
+    // Hetzner only accepts CIDRs, so a bare address is stored as a single-host network
+    fn with_cidr_sources(&self) -> FirewallRule {
+        let source_ips = self
+            .source_ips
+            .iter()
+            .map(|source| match source.parse::<IpAddr>() {
+                Ok(IpAddr::V4(_)) => format!("{}/32", source),
+                Ok(IpAddr::V6(_)) => format!("{}/128", source),
+                Err(_) => source.clone(),
+            })
+            .collect();
+        FirewallRule { source_ips, ..self.clone() }
+    }
+
+    fn matches(&self, rule_match: &FirewallRuleMatch) -> bool {
+        self.direction == "in" && self.protocol == rule_match.protocol && self.port == rule_match.port
+    }
+}
+
+// Ping plus every port the app declares, open to everyone until the user narrows them down
+pub fn app_firewall_rules(app: &AppDefinition) -> Vec<FirewallRule> {
+    let mut rules = vec![FirewallRule {
+        direction: default_direction(),
+        protocol: "icmp".into(),
+        port: None,
+        source_ips: any_source(),
+        description: Some("Ping".into()),
+    }];
+
+    rules.extend(app.ports.iter().map(|port| FirewallRule {
+        direction: default_direction(),
+        protocol: port.protocol.clone(),
+        port: Some(port.port.to_string()),
+        source_ips: any_source(),
+        description: Some(format!("{}: {}", app.name, port.description)).filter(|_| !port.description.is_empty()),
+    }));
+
+    rules
+}
+
+fn current_rules(variables: &Map<String, Value>) -> Result<Vec<FirewallRule>, String> {
+    match variables.get(FIREWALL_RULES_VARIABLE) {
+        Some(rules) => serde_json::from_value(rules.clone()).map_err(|e| format!("Stored firewall rules are invalid: {}", e)),
+        None => Err("This deployment's template does not manage firewall rules".into()),
+    }
+}
+
+pub async fn get_firewall_rules(path: web::Path<String>, query: web::Query<FirewallQuery>) -> impl Responder {
+    let project_id = path.into_inner();
+    let mongo_client = init_mongo_client().await;
+
+    let deployment = match find_user_deployment(mongo_client, &query.user_email, &project_id).await {
+        Ok(deployment) => deployment,
+        Err(resp) => return resp,
+    };
+
+    match deployment_variables(&deployment).ok_or_else(|| "This deployment has no recorded variables".to_string()).and_then(|variables| current_rules(&variables)) {
+        Ok(rules) => HttpResponse::Ok().json(ApiResponse {
+            status: "success".into(),
+            message: "Firewall rules fetched successfully".into(),
+            returneddata: Some(json!({ "rules": rules })),
+        }),
+        Err(message) => error_response(HttpResponse::Conflict(), message),
+    }
+}
+
+// Applies removals then additions to the deployment's rules and queues a reapply of its current template version
+pub async fn update_firewall_rules(store: web::Data<dyn ArtifactStore>, job_queue: web::Data<JobQueue>, path: web::Path<String>, request: web::Json<FirewallUpdateRequest>) -> impl Responder {
+    let project_id = path.into_inner();
+    println!("📥 Received firewall update for {}: {:?}", project_id, request);
+
+    if request.add.is_empty() && request.remove.is_empty() {
+        return error_response(HttpResponse::BadRequest(), "Nothing to change, give rules to add or remove");
+    }
+
+    let mongo_client = init_mongo_client().await;
+    let deployment = match find_running_deployment(mongo_client.clone(), &request.user_email, &project_id, "reconfigured").await {
+        Ok(deployment) => deployment,
+        Err(resp) => return resp,
+    };
+
+    let provider = match deployment_provider(&deployment) {
+        Ok(provider) => provider,
+        Err(e) => return error_response(HttpResponse::Conflict(), e),
+    };
+    if let Some(e) = request.add.iter().find_map(|rule| rule.validate(provider).err()) {
+        return error_response(HttpResponse::BadRequest(), e);
+    }
+
+    let (Some(template), Some(mut variables)) = (deployment_template(&deployment), deployment_variables(&deployment)) else {
+        return error_response(HttpResponse::Conflict(), "This deployment predates versioned templates and its firewall cannot be changed");
+    };
+
+    let mut rules = match current_rules(&variables) {
+        Ok(rules) => rules,
+        Err(message) => return error_response(HttpResponse::Conflict(), message),
+    };
+
+    for rule_match in &request.remove {
+        let before = rules.len();
+        rules.retain(|rule| !rule.matches(rule_match));
+        if rules.len() == before {
+            let port = rule_match.port.as_deref().map(|port| format!(" port {}", port)).unwrap_or_default();
+            return error_response(HttpResponse::BadRequest(), format!("No {}{} rule to remove", rule_match.protocol, port));
+        }
+    }
+    rules.extend(request.add.iter().map(FirewallRule::with_cidr_sources));
+
+    let rules = match serde_json::to_value(&rules) {
+        Ok(rules) => rules,
+        Err(e) => {
+            eprintln!("❌ Failed to encode firewall rules: {}", e);
+            return error_response(HttpResponse::InternalServerError(), "Failed to encode firewall rules");
+        }
+    };
+    variables.insert(FIREWALL_RULES_VARIABLE.into(), rules);
+
+    let credential = match CredentialRef::for_deployment(mongo_client.clone(), &deployment).await {
+        Ok(credential) => credential,
+        Err(e) => return error_response(HttpResponse::BadRequest(), format!("{}. Cannot update the firewall.", e)),
+    };
+
+    let user_data = deployment_user_data(&deployment);
+
+    // The changed rules still have to fit the variable the pinned template version declares
+    let prefix = template_version_prefix(&template.name, &template.version);
+    let manifest = match read_manifest(store.get_ref(), &prefix, &template.name).await {
+        Ok(Some(manifest)) => manifest,
+        Ok(None) => return error_response(HttpResponse::InternalServerError(), format!("{} has no manifest", prefix)),
+        Err(e) => {
+            eprintln!("❌ Error reading manifest {}: {}", prefix, e);
+            return error_response(HttpResponse::InternalServerError(), "Failed to load the deployment's template");
+        }
+    };
+    let variables = match validate_variables(&manifest, &variables, &secret_variable_names(credential.provider, user_data.is_some())) {
+        Ok(variables) => variables,
+        Err(err_msg) => return error_response(HttpResponse::BadRequest(), err_msg),
+    };
+
+    let job_id = Uuid::new_v4().to_string();
+    // The rules above were computed from the deployment as it is now; a queued change would be silently undone
+    if let Err(e) = create_job(mongo_client.clone(), &job_id, &project_id, "firewall update", &request.user_email).await {
+        if is_duplicate_key(&e) {
+            return error_response(HttpResponse::Conflict(), "Another change to this deployment is queued or running, try again once it finishes");
+        }
+        eprintln!("❌ Failed to create firewall job: {}", e);
+        return error_response(HttpResponse::InternalServerError(), "Failed to create firewall update job");
+    }
+
+    let job = ReapplyJob {
+        job_id: job_id.clone(),
+        project_id: project_id.clone(),
+        project_name: deployment.get_str("project_name").unwrap_or_default().to_string(),
+        operation: "firewall update".into(),
+        credential,
+        template,
+        variables,
+        user_data,
+        reviewed_plan: None,
+    };
+
+    if let Err(e) = job_queue.enqueue(Job::Reapply(Box::new(job))) {
+        eprintln!("❌ Failed to queue firewall job {}: {}", job_id, e);
+        if let Err(e) = update_job_status(mongo_client, &job_id, "failed", &e, None).await {
+            eprintln!("❌ Failed to mark job {} as failed: {}", job_id, e);
+        }
+        return error_response(HttpResponse::ServiceUnavailable(), e);
+    }
+
+    HttpResponse::Accepted().json(ApiResponse {
+        status: "success".into(),
+        message: "Firewall update queued".into(),
+        returneddata: Some(json!({ "job_id": job_id, "project_id": project_id })),
+    })
+}
@@ -0,0 +1,98 @@
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeploymentState {
+    Queued,
+    CopyingTemplates,
+    Provisioning,
+    Running,
+    Updating,
+    Failed,
+    Destroying,
+    Destroyed,
+}
+
+impl DeploymentState {
+    pub const ALL: [DeploymentState; 8] = [
+        DeploymentState::Queued,
+        DeploymentState::CopyingTemplates,
+        DeploymentState::Provisioning,
+        DeploymentState::Running,
+        DeploymentState::Updating,
+        DeploymentState::Failed,
+        DeploymentState::Destroying,
+        DeploymentState::Destroyed,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeploymentState::Queued => "queued",
+            DeploymentState::CopyingTemplates => "copying_templates",
+            DeploymentState::Provisioning => "provisioning",
+            DeploymentState::Running => "running",
+            DeploymentState::Updating => "updating",
+            DeploymentState::Failed => "failed",
+            DeploymentState::Destroying => "destroying",
+            DeploymentState::Destroyed => "destroyed",
+        }
+    }
+
+    // States a deployment is allowed to be in before moving into `self`
+    pub fn allowed_sources(&self) -> &'static [DeploymentState] {
+        match self {
+            DeploymentState::Queued => &[],
+            DeploymentState::CopyingTemplates => &[DeploymentState::Queued],
+            DeploymentState::Provisioning => &[DeploymentState::CopyingTemplates],
+            DeploymentState::Running => &[DeploymentState::Provisioning, DeploymentState::Updating],
+            DeploymentState::Updating => &[DeploymentState::Running],
+            DeploymentState::Failed => &[
+                DeploymentState::Queued,
+                DeploymentState::CopyingTemplates,
+                DeploymentState::Provisioning,
+                DeploymentState::Updating,
+                DeploymentState::Destroying,
+            ],
+            DeploymentState::Destroying => &[DeploymentState::Running, DeploymentState::Failed],
+            DeploymentState::Destroyed => &[DeploymentState::Destroying],
+        }
+    }
+
+    // Deployments in these states have a worker or request actively driving them
+    pub fn is_in_progress(&self) -> bool {
+        matches!(
+            self,
+            DeploymentState::Queued
+                | DeploymentState::CopyingTemplates
+                | DeploymentState::Provisioning
+                | DeploymentState::Updating
+                | DeploymentState::Destroying
+        )
+    }
+}
+
+impl fmt::Display for DeploymentState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug)]
+pub enum TransitionError {
+    NotFound,
+    Illegal { from: String, to: DeploymentState },
+    Database(mongodb::error::Error),
+}
+
+impl fmt::Display for TransitionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransitionError::NotFound => write!(f, "Deployment not found"),
+            TransitionError::Illegal { from, to } => {
+                write!(f, "Cannot move deployment from '{}' to '{}'", from, to)
+            }
+            TransitionError::Database(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for TransitionError {}
@@ -1,2 +1,12 @@
 pub mod deploy;
 pub mod deployments;
+pub mod lifecycle;
+pub mod deployment_logs;
+pub mod deployment_runs;
+pub mod plan;
+pub mod operation_lock;
+pub mod reapply;
+pub mod upgrade;
+pub mod cloud_init;
+pub mod firewall;
+pub mod ssh_key;
\ No newline at end of file
@@ -0,0 +1,150 @@
+use std::fmt;
+use std::time::Duration;
+use mongodb::Client;
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+use crate::utils::database::db::{find_deployment_lock, release_deployment_lock, renew_deployment_lock, try_acquire_deployment_lock};
+
+// How long a lock survives without a heartbeat, i.e. how long a crashed holder blocks the deployment
+pub const LEASE_DURATION: Duration = Duration::from_secs(120);
+// Renew well before the lease runs out so a slow database round trip never lets it lapse
+const RENEW_INTERVAL: Duration = Duration::from_secs(40);
+
+#[derive(Debug)]
+pub enum LockError {
+    Held { operation: String, acquired_at: String },
+    NotFound,
+    Database(mongodb::error::Error),
+}
+
+impl fmt::Display for LockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LockError::Held { operation, acquired_at } => {
+                write!(f, "Deployment is locked by an in-progress '{}' operation (started {})", operation, acquired_at)
+            }
+            LockError::NotFound => write!(f, "Deployment not found"),
+            LockError::Database(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for LockError {}
+
+// Lease on a deployment document held for the duration of one Terraform operation
+pub struct OperationLock {
+    mongo_client: Client,
+    project_id: String,
+    holder_id: String,
+    heartbeat: JoinHandle<()>,
+    released: bool,
+}
+
+impl OperationLock {
+    pub async fn acquire(mongo_client: Client, project_id: &str, operation: &str) -> Result<OperationLock, LockError> {
+        let holder_id = Uuid::new_v4().to_string();
+
+        let acquired = try_acquire_deployment_lock(mongo_client.clone(), project_id, &holder_id, operation, LEASE_DURATION)
+            .await
+            .map_err(LockError::Database)?;
+
+        if !acquired {
+            return match find_deployment_lock(mongo_client, project_id).await.map_err(LockError::Database)? {
+                Some((operation, acquired_at)) => Err(LockError::Held { operation, acquired_at }),
+                None => Err(LockError::NotFound),
+            };
+        }
+
+        println!("🔒 Acquired '{}' lock on deployment {}", operation, project_id);
+        println!("--------------------------------------------------------");
+
+        Ok(OperationLock::hold(mongo_client, project_id, holder_id))
+    }
+
+    // Takes over a lock written into the deployment when it was created, as long as `holder_id` still holds it
+    pub async fn adopt(mongo_client: Client, project_id: &str, holder_id: &str, operation: &str) -> Result<OperationLock, LockError> {
+        let renewed = renew_deployment_lock(mongo_client.clone(), project_id, holder_id, LEASE_DURATION)
+            .await
+            .map_err(LockError::Database)?;
+
+        if !renewed {
+            return match find_deployment_lock(mongo_client, project_id).await.map_err(LockError::Database)? {
+                Some((operation, acquired_at)) => Err(LockError::Held { operation, acquired_at }),
+                None => Err(LockError::NotFound),
+            };
+        }
+
+        println!("🔒 Took over '{}' lock on deployment {}", operation, project_id);
+        println!("--------------------------------------------------------");
+
+        Ok(OperationLock::hold(mongo_client, project_id, holder_id.to_string()))
+    }
+
+    fn hold(mongo_client: Client, project_id: &str, holder_id: String) -> OperationLock {
+        // Keep extending the lease while the operation runs; if this process dies the lease simply expires
+        let heartbeat = {
+            let mongo_client = mongo_client.clone();
+            let project_id = project_id.to_string();
+            let holder_id = holder_id.clone();
+
+            tokio::spawn(async move {
+                loop {
+                    tokio::time::sleep(RENEW_INTERVAL).await;
+                    match renew_deployment_lock(mongo_client.clone(), &project_id, &holder_id, LEASE_DURATION).await {
+                        Ok(true) => {}
+                        Ok(false) => {
+                            eprintln!("⚠️ Lost the lock on deployment {}, stopping heartbeat", project_id);
+                            break;
+                        }
+                        Err(e) => eprintln!("⚠️ Failed to renew lock on deployment {}: {}", project_id, e),
+                    }
+                }
+            })
+        };
+
+        OperationLock {
+            mongo_client,
+            project_id: project_id.to_string(),
+            holder_id,
+            heartbeat,
+            released: false,
+        }
+    }
+
+    pub async fn release(mut self) {
+        self.heartbeat.abort();
+        self.released = true;
+        release_lock(self.mongo_client.clone(), &self.project_id, &self.holder_id).await;
+    }
+}
+
+async fn release_lock(mongo_client: Client, project_id: &str, holder_id: &str) {
+    match release_deployment_lock(mongo_client, project_id, holder_id).await {
+        Ok(()) => {
+            println!("🔓 Released lock on deployment {}", project_id);
+            println!("--------------------------------------------------------");
+        }
+        Err(e) => eprintln!("⚠️ Failed to release lock on deployment {} (it will expire): {}", project_id, e),
+    }
+}
+
+// A lock dropped without release(), e.g. when a client disconnects mid-request or a job panics,
+// must not keep renewing its lease forever
+impl Drop for OperationLock {
+    fn drop(&mut self) {
+        self.heartbeat.abort();
+        if self.released {
+            return;
+        }
+
+        eprintln!("⚠️ Lock on deployment {} was dropped without being released", self.project_id);
+        let (mongo_client, project_id, holder_id) = (self.mongo_client.clone(), self.project_id.clone(), self.holder_id.clone());
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                runtime.spawn(async move { release_lock(mongo_client, &project_id, &holder_id).await });
+            }
+            Err(_) => eprintln!("⚠️ No runtime to release the lock on deployment {}, it will expire", project_id),
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use actix_web::{web, HttpResponse, Responder};
+use serde_json::{json, Value};
+use tokio::fs as async_fs;
+use uuid::Uuid;
+
+use crate::{
+    deploy::{request_template, resolve_variables, validate_request, verify_deploy_user, ApiResponse, DeploymentRequest},
+    s3_handler::render_template_to_local_dir,
+    terraform_handler::{create_project_temp_folder, run_terraform_plan_commands},
+    utils::deployment::cloud_init::USER_DATA_VARIABLE,
+    utils::settings::keyring::Keyring,
+    utils::storage::artifact_store::ArtifactStore,
+    utils::templates::versions::{resolve_template_version, template_version_prefix, verify_template_version},
+    utils::terraform::variables::{placeholder_replacements, write_tfvars},
+    utils::terraform::diagnostics::terraform_error_details,
+    utils::terraform::run_record::RunLog,
+    utils::user::signup_func::init_mongo_client,
+};
+
+// Keeps only what a client needs to preview each change from `terraform show -json`
+pub fn summarize_resource_changes(plan: &Value) -> (Vec<Value>, Value) {
+    let mut changes = Vec::new();
+    let (mut create, mut update, mut delete) = (0, 0, 0);
+
+    for change in plan["resource_changes"].as_array().into_iter().flatten() {
+        let actions: Vec<&str> = change["change"]["actions"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|action| action.as_str())
+            .collect();
+
+        if actions == ["no-op"] || actions == ["read"] {
+            continue;
+        }
+        if actions.contains(&"create") {
+            create += 1;
+        }
+        if actions.contains(&"update") {
+            update += 1;
+        }
+        if actions.contains(&"delete") {
+            delete += 1;
+        }
+
+        // Drop attributes Terraform marks as sensitive before they reach the client
+        let mut after = change["change"]["after"].clone();
+        if let (Some(after), Some(sensitive)) = (after.as_object_mut(), change["change"]["after_sensitive"].as_object()) {
+            for (key, flag) in sensitive {
+                if flag.as_bool() == Some(true) {
+                    after.remove(key);
+                }
+            }
+        }
+
+        changes.push(json!({
+            "address": change["address"],
+            "type": change["type"],
+            "name": change["name"],
+            "provider": change["provider_name"],
+            "actions": actions,
+            "after": after,
+        }));
+    }
+
+    (changes, json!({ "create": create, "update": update, "delete": delete }))
+}
+
+pub async fn plan_deployment(store: web::Data<dyn ArtifactStore>, keyring: web::Data<Keyring>, deploymentrequest: web::Json<DeploymentRequest>) -> impl Responder {
+    println!("📥 Received plan request: {:?}", deploymentrequest);
+
+    let provider = match validate_request(&deploymentrequest) {
+        Ok(provider) => provider,
+        Err(err_msg) => {
+            println!("❌ Validation failed: {}", err_msg);
+            return HttpResponse::BadRequest().json(ApiResponse {
+                status: "error".into(),
+                message: err_msg,
+                returneddata: None,
+            });
+        }
+    };
+
+    let mongo_client = init_mongo_client().await;
+    let (_user_id, credential) = match verify_deploy_user(mongo_client.clone(), provider, &deploymentrequest).await {
+        Ok(found) => found,
+        Err(resp) => return resp,
+    };
+
+    let (template, manifest) = match resolve_template_version(mongo_client.clone(), store.get_ref(), request_template(provider, &deploymentrequest), deploymentrequest.template_version.as_deref()).await {
+        Ok(resolved) => resolved,
+        Err(resp) => return resp,
+    };
+
+    // A preview of files that were changed after publishing would not show what a deploy is allowed to run
+    if let Err(e) = verify_template_version(store.get_ref(), &template).await {
+        eprintln!("❌ Template integrity error: {}", e);
+        return HttpResponse::InternalServerError().json(ApiResponse {
+            status: "error".into(),
+            message: e.to_string(),
+            returneddata: None,
+        });
+    }
+
+    let (variables, user_data) = match resolve_variables(store.get_ref(), provider, &manifest, &deploymentrequest).await {
+        Ok(resolved) => resolved,
+        Err(err_msg) => {
+            println!("❌ Variable validation failed: {}", err_msg);
+            return HttpResponse::BadRequest().json(ApiResponse {
+                status: "error".into(),
+                message: err_msg,
+                returneddata: None,
+            });
+        }
+    };
+
+    let secrets = match credential.secrets(mongo_client, &keyring).await {
+        Ok(mut secrets) => {
+            // Nothing is stored for a plan, so the cloud-init goes to Terraform as it is
+            if let Some(user_data) = user_data {
+                secrets.insert(USER_DATA_VARIABLE.into(), user_data);
+            }
+            secrets
+        }
+        Err(err_msg) => {
+            return HttpResponse::BadRequest().json(ApiResponse {
+                status: "error".into(),
+                message: format!("{}. Cannot plan the deployment.", err_msg),
+                returneddata: None,
+            });
+        }
+    };
+
+    let plan_id = Uuid::new_v4().to_string();
+    let source_prefix = template_version_prefix(&template.name, &template.version);
+    let replacements = placeholder_replacements(&manifest, &variables);
+
+    let local_dir = match create_project_temp_folder(&deploymentrequest.project_name, &plan_id) {
+        Ok(dir) => dir,
+        Err(e) => {
+            eprintln!("❌ Failed to create plan folder: {}", e);
+            return HttpResponse::InternalServerError().json(ApiResponse {
+                status: "error".into(),
+                message: "Failed to prepare a working folder for the plan".into(),
+                returneddata: None,
+            });
+        }
+    };
+
+    let response = match render_template_to_local_dir(store.get_ref(), &source_prefix, &local_dir, &manifest.templated_files, &replacements).await {
+        Ok((0, _)) => HttpResponse::InternalServerError().json(ApiResponse {
+            status: "error".into(),
+            message: format!("Template '{}' has no files", template.name),
+            returneddata: None,
+        }),
+        Ok((_, unused_placeholders)) => {
+            let mut output = RunLog::detached();
+            let result = match write_tfvars(&local_dir, &variables).await {
+                Ok(()) => run_terraform_plan_commands(&local_dir, &secrets, &mut output).await,
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(plan) => {
+                    let (resource_changes, summary) = summarize_resource_changes(&plan);
+                    HttpResponse::Ok().json(ApiResponse {
+                        status: "success".into(),
+                        message: "Plan generated, nothing was applied".into(),
+                        returneddata: Some(json!({
+                            "summary": summary,
+                            "resource_changes": resource_changes,
+                            "unused_placeholders": unused_placeholders,
+                        })),
+                    })
+                }
+                Err(e) => {
+                    eprintln!("❌ Terraform plan failed: {}", e);
+                    HttpResponse::UnprocessableEntity().json(ApiResponse {
+                        status: "error".into(),
+                        message: format!("Terraform plan failed: {}", e),
+                        returneddata: terraform_error_details(e.as_ref()),
+                    })
+                }
+            }
+        }
+        Err(e) => {
+            eprintln!("❌ Template render error: {}", e);
+            HttpResponse::InternalServerError().json(ApiResponse {
+                status: "error".into(),
+                message: format!("Failed to render templates for the plan: {}", e),
+                returneddata: None,
+            })
+        }
+    };
+
+    // Plans are throwaway, nothing in the folder is kept
+    if let Err(e) = async_fs::remove_dir_all(&local_dir).await {
+        eprintln!("⚠️ Failed to delete plan folder {}: {}", local_dir.display(), e);
+    }
+
+    response
+}
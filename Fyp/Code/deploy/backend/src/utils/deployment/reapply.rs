@@ -0,0 +1,130 @@
+use mongodb::Client;
+
+use crate::{
+    deploy::{advance_deployment, deployment_prefix, fail_deployment, render_deployment},
+    s3_handler::{delete_specific_deployment_folder, swap_in_staged_folder},
+    terraform_handler::execute_deployment,
+    utils::database::db::{store_deployment_configuration, store_deployment_outputs, transition_deployment_state},
+    utils::deployment::cloud_init::add_user_data,
+    utils::deployment::lifecycle::DeploymentState,
+    utils::deployment::operation_lock::OperationLock,
+    utils::jobs::job_queue::{JobFailure, ReapplyJob},
+    utils::settings::keyring::Keyring,
+    utils::storage::artifact_store::ArtifactStore,
+    utils::terraform::diagnostics::{terraform_error_details, PlanChanged},
+    utils::terraform::log_stream::LogHub,
+    utils::terraform::run_record::{finish_terraform_run, start_terraform_run, RunLog},
+};
+
+pub async fn run_reapply_job(mongo_client: Client, store: &dyn ArtifactStore, keyring: &Keyring, log_hub: &LogHub, job: ReapplyJob) -> Result<Vec<String>, JobFailure> {
+    // A deployment that cannot be locked is left exactly as it was
+    let lock = OperationLock::acquire(mongo_client.clone(), &job.project_id, &job.operation)
+        .await
+        .map_err(|e| format!("Could not lock deployment: {}", e))?;
+
+    let result = reapply_deployment(mongo_client, store, keyring, log_hub, &job).await;
+    lock.release().await;
+    result
+}
+
+async fn delete_staging_folder(store: &dyn ArtifactStore, staging_prefix: &str) {
+    if let Err(e) = delete_specific_deployment_folder(store, staging_prefix).await {
+        eprintln!("⚠️ Failed to delete {}: {}", staging_prefix, e);
+    }
+}
+
+// For failures before Terraform has run: the infrastructure is untouched, so the deployment goes back to running
+// and only the job records what went wrong
+async fn abandon_reapply(mongo_client: Client, project_id: &str, operation: &str, reason: String) -> JobFailure {
+    eprintln!("❌ {} for {} abandoned: {}", operation, project_id, reason);
+    let note = format!("{} abandoned before anything was applied", operation);
+    if let Err(e) = transition_deployment_state(mongo_client, project_id, DeploymentState::Running, &note).await {
+        eprintln!("❌ Failed to move deployment {} back to running: {}", project_id, e);
+    }
+    reason.into()
+}
+
+async fn reapply_deployment(mongo_client: Client, store: &dyn ArtifactStore, keyring: &Keyring, log_hub: &LogHub, job: &ReapplyJob) -> Result<Vec<String>, JobFailure> {
+    let project_id = &job.project_id;
+    let destination_prefix = deployment_prefix(&job.project_name, project_id);
+
+    advance_deployment(mongo_client.clone(), project_id, DeploymentState::Updating, &format!("Applying {}", job.operation)).await?;
+
+    let secrets = match job.credential.secrets(mongo_client.clone(), keyring).await.and_then(|mut secrets| {
+        add_user_data(&mut secrets, keyring, project_id, job.user_data.as_ref())?;
+        Ok(secrets)
+    }) {
+        Ok(secrets) => secrets,
+        Err(reason) => return Err(abandon_reapply(mongo_client, project_id, &job.operation, reason).await),
+    };
+
+    // Rendered and applied beside the live folder, which keeps matching the recorded configuration until the apply succeeds.
+    // The copy keeps the deployment's folder name, which is how Terraform runs find the project
+    let staging_prefix = format!("staging/{}/{}", job.job_id, destination_prefix);
+    let unused_placeholders = match render_deployment(store, &job.template, &staging_prefix, project_id, &job.variables).await {
+        Ok(unused) => unused,
+        Err(reason) => {
+            delete_staging_folder(store, &staging_prefix).await;
+            return Err(abandon_reapply(mongo_client, project_id, &job.operation, reason).await);
+        }
+    };
+
+    let log = log_hub.channel(project_id);
+    let mut output = RunLog::new(&log);
+    let command = format!("terraform init && terraform plan -out=tfplan && terraform apply tfplan ({})", job.operation);
+    let run = start_terraform_run(mongo_client.clone(), project_id, &command).await;
+
+    // State lives in the backend keyed by project id, so applying from the staged copy changes the same infrastructure
+    let result = execute_deployment(store, &staging_prefix, project_id, &secrets, job.reviewed_plan.as_deref(), &mut output).await;
+
+    match &run {
+        Ok(run) => {
+            let error = result.as_ref().err().map(|e| e.to_string());
+            finish_terraform_run(mongo_client.clone(), store, run, &output, error).await;
+        }
+        Err(e) => eprintln!("⚠️ Failed to create run record for {}: {}", project_id, e),
+    }
+    drop(output);
+    log.finish();
+
+    // Unlike a fresh deployment there is live infrastructure here, so a failed apply is never rolled back automatically
+    let outputs = match result {
+        Ok(outputs) => outputs,
+        // Refused before the apply, so the infrastructure still matches the recorded configuration
+        Err(e) if e.is::<PlanChanged>() => {
+            delete_staging_folder(store, &staging_prefix).await;
+            return Err(abandon_reapply(mongo_client, project_id, &job.operation, e.to_string()).await);
+        }
+        Err(e) => {
+            eprintln!("❌ {} failed for {}: {}", job.operation, project_id, e);
+            let reason = format!("Failed to apply {}: {}", job.operation, e);
+            delete_staging_folder(store, &staging_prefix).await;
+            fail_deployment(mongo_client, project_id, &reason).await;
+            return Err(JobFailure {
+                message: reason,
+                details: terraform_error_details(e.as_ref()),
+            });
+        }
+    };
+
+    if let Err(e) = store_deployment_outputs(mongo_client.clone(), project_id, &outputs).await {
+        eprintln!("⚠️ Failed to store outputs for {}: {}", project_id, e);
+    }
+
+    if let Err(e) = store_deployment_configuration(mongo_client.clone(), project_id, &job.template, &job.variables).await {
+        eprintln!("⚠️ Failed to record new configuration for {}: {}", project_id, e);
+    }
+
+    // Files dropped from the template must not linger in the deployment folder. The staged copy is kept if this fails,
+    // since it is the only configuration matching what was just applied
+    if let Err(e) = swap_in_staged_folder(store, &staging_prefix, &destination_prefix).await {
+        eprintln!("❌ Failed to move {} into {}: {}", staging_prefix, destination_prefix, e);
+        let reason = format!("{} was applied but the deployment folder could not be updated from {}", job.operation, staging_prefix);
+        fail_deployment(mongo_client, project_id, &reason).await;
+        return Err(reason.into());
+    }
+
+    advance_deployment(mongo_client, project_id, DeploymentState::Running, &format!("{} applied", job.operation)).await?;
+
+    Ok(unused_placeholders)
+}
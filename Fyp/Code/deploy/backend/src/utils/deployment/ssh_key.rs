@@ -0,0 +1,104 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+// Template variable the key is handed to; templates that can install it declare it
+pub const SSH_PUBLIC_KEY_VARIABLE: &str = "ssh_public_key";
+
+// Key types OpenSSH writes to `*.pub` files
+const KEY_TYPES: [&str; 6] = [
+    "ssh-ed25519",
+    "ssh-rsa",
+    "ecdsa-sha2-nistp256",
+    "ecdsa-sha2-nistp384",
+    "ecdsa-sha2-nistp521",
+    "sk-ssh-ed25519@openssh.com",
+];
+
+// Checks `<type> <base64 blob> [comment]` and that the blob really encodes a key of that type.
+// Returns the key on a single line, with the comment kept, ready to hand to the provider.
+pub fn validate_public_key(key: &str) -> Result<String, String> {
+    let key = key.trim();
+    if key.lines().count() != 1 {
+        return Err("SSH key must be a single OpenSSH public key line".into());
+    }
+
+    let mut parts = key.split_whitespace();
+    let (Some(key_type), Some(blob)) = (parts.next(), parts.next()) else {
+        return Err("SSH key must look like '<type> <key> [comment]'".into());
+    };
+    let comment: Vec<&str> = parts.collect();
+
+    if !KEY_TYPES.contains(&key_type) {
+        return Err(format!("Unsupported SSH key type '{}'", key_type));
+    }
+
+    let decoded = STANDARD.decode(blob).map_err(|_| "SSH key data is not valid base64".to_string())?;
+
+    // The blob starts with its own type as a length-prefixed string, which must agree with the first field
+    let embedded_type = decoded
+        .get(..4)
+        .map(|len| u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize)
+        .and_then(|len| decoded.get(4..4 + len));
+    if embedded_type != Some(key_type.as_bytes()) {
+        return Err("SSH key data does not match its key type".into());
+    }
+    if decoded.len() <= 4 + key_type.len() {
+        return Err("SSH key data is truncated".into());
+    }
+
+    let mut normalized = format!("{} {}", key_type, blob);
+    if !comment.is_empty() {
+        normalized.push(' ');
+        normalized.push_str(&comment.join(" "));
+    }
+    Ok(normalized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ED25519_KEY: &str = "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIB+ANtoX/5NcH2vlsz3Mc8Zr5PBZYAYrkeZopSmMNpmk";
+    const RSA_KEY: &str = "ssh-rsa AAAAB3NzaC1yc2EAAAADAQABAAAAgQDCOW/6ryrh1HA9BhdQRiAMrf5MuwufIklus/FoTaBSnjhNkHhCVuiZQ9zCu/EhYN4Kq0btcJZVtkBPyqe5AwvgXoaH2JJtlkkjYMvQyUzIAgVbTlYWNQXwCTnK9Qr44ACetACW9xgjNADYiRU2KWjcKV6/u5P0ZCgSgIPlgyHFrQ==";
+    const ECDSA_KEY: &str = "ecdsa-sha2-nistp256 AAAAE2VjZHNhLXNoYTItbmlzdHAyNTYAAAAIbmlzdHAyNTYAAABBBH7RT+9YnjWzhkzJnFfl/z9SGKf/BUWj669S5fx30jwpMU7H9JIuM6SG6zS0iH1l2ScdASpy0Kdgh84AxEq/0kc=";
+
+    #[test]
+    fn accepts_valid_keys() {
+        assert_eq!(validate_public_key(ED25519_KEY).unwrap(), ED25519_KEY);
+        assert_eq!(validate_public_key(RSA_KEY).unwrap(), RSA_KEY);
+        assert_eq!(validate_public_key(ECDSA_KEY).unwrap(), ECDSA_KEY);
+    }
+
+    #[test]
+    fn normalizes_whitespace_and_keeps_the_comment() {
+        let key = format!("  {}\tuser@laptop  work \n", ED25519_KEY.replace(' ', "   "));
+        assert_eq!(validate_public_key(&key).unwrap(), format!("{} user@laptop work", ED25519_KEY));
+    }
+
+    #[test]
+    fn rejects_a_blob_of_another_key_type() {
+        let blob = ED25519_KEY.split_whitespace().nth(1).unwrap();
+        let error = validate_public_key(&format!("ssh-rsa {}", blob)).unwrap_err();
+        assert!(error.contains("does not match"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_a_truncated_blob() {
+        // Only the length-prefixed type, no key material after it
+        let blob = STANDARD.encode([&11u32.to_be_bytes()[..], b"ssh-ed25519"].concat());
+        let error = validate_public_key(&format!("ssh-ed25519 {}", blob)).unwrap_err();
+        assert!(error.contains("truncated"), "{}", error);
+
+        // Cut off inside the type itself
+        let blob = STANDARD.encode([&11u32.to_be_bytes()[..], b"ssh-ed"].concat());
+        assert!(validate_public_key(&format!("ssh-ed25519 {}", blob)).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        assert!(validate_public_key("").is_err());
+        assert!(validate_public_key("ssh-ed25519").is_err());
+        assert!(validate_public_key("ssh-dss AAAAB3NzaC1kc3M=").is_err());
+        assert!(validate_public_key("ssh-ed25519 not*base64").is_err());
+        assert!(validate_public_key(&format!("{}\n{}", ED25519_KEY, ED25519_KEY)).is_err());
+    }
+}
@@ -0,0 +1,323 @@
+use actix_web::{web, HttpResponse, Responder};
+use mongodb::{bson::Document, Client};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use tokio::fs as async_fs;
+use uuid::Uuid;
+
+use crate::{
+    deploy::ApiResponse,
+    s3_handler::render_template_to_local_dir,
+    terraform_handler::{create_project_temp_folder, plan_fingerprint, run_terraform_plan_commands},
+    utils::database::db::{create_job, is_duplicate_key, find_deployment, find_user_by_email, store_pending_upgrade, update_job_status},
+    utils::deployment::cloud_init::{add_user_data, deployment_user_data, secret_variable_names},
+    utils::deployment::lifecycle::DeploymentState,
+    utils::deployment::operation_lock::{LockError, OperationLock},
+    utils::deployment::plan::summarize_resource_changes,
+    utils::jobs::job_queue::{Job, JobQueue, ReapplyJob},
+    utils::settings::credentials::CredentialRef,
+    utils::settings::keyring::Keyring,
+    utils::storage::artifact_store::ArtifactStore,
+    utils::templates::versions::{resolve_template_version, template_version_prefix, verify_template_version, TemplateVersion},
+    utils::terraform::diagnostics::terraform_error_details,
+    utils::terraform::run_record::RunLog,
+    utils::terraform::variables::{placeholder_replacements, validate_variables, write_tfvars},
+    utils::user::signup_func::init_mongo_client,
+};
+
+#[derive(Deserialize, Debug)]
+pub struct UpgradePlanRequest {
+    pub user_email: String,
+    // Version to move to; the latest published one when left out
+    #[serde(default)]
+    pub template_version: Option<String>,
+    // Values for variables the new version adds or changes; everything else carries over
+    #[serde(default)]
+    pub variables: Map<String, Value>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct UpgradeApplyRequest {
+    pub user_email: String,
+    pub template_version: String,
+}
+
+pub fn error_response(mut builder: actix_web::HttpResponseBuilder, message: impl Into<String>) -> HttpResponse {
+    builder.json(ApiResponse {
+        status: "error".into(),
+        message: message.into(),
+        returneddata: None,
+    })
+}
+
+// Loads the deployment if it belongs to the user
+pub async fn find_user_deployment(mongo_client: Client, user_email: &str, project_id: &str) -> Result<Document, HttpResponse> {
+    let user_id = match find_user_by_email(mongo_client.clone(), user_email).await {
+        Ok(Some(user_doc)) => match user_doc.get_object_id("_id") {
+            Ok(oid) => oid,
+            Err(_) => return Err(error_response(HttpResponse::InternalServerError(), "Failed to extract user ID from user document")),
+        },
+        Ok(None) => return Err(error_response(HttpResponse::BadRequest(), format!("User with email '{}' not found", user_email))),
+        Err(e) => {
+            eprintln!("❌ Error finding user by email: {}", e);
+            return Err(error_response(HttpResponse::InternalServerError(), "Internal server error while looking up user"));
+        }
+    };
+
+    match find_deployment(mongo_client, &user_id, project_id).await {
+        Ok(Some(deployment)) => Ok(deployment),
+        Ok(None) => Err(error_response(HttpResponse::NotFound(), "No matching deployment found for the given project_id")),
+        Err(e) => {
+            eprintln!("❌ Error finding deployment: {}", e);
+            Err(error_response(HttpResponse::InternalServerError(), "Internal server error while looking up deployment"))
+        }
+    }
+}
+
+// Like find_user_deployment, but only running deployments can be changed in place
+pub async fn find_running_deployment(mongo_client: Client, user_email: &str, project_id: &str, action: &str) -> Result<Document, HttpResponse> {
+    let deployment = find_user_deployment(mongo_client, user_email, project_id).await?;
+
+    let status = deployment.get_str("status").unwrap_or("unknown");
+    if status != DeploymentState::Running.as_str() {
+        return Err(error_response(HttpResponse::Conflict(), format!("Only running deployments can be {} (this one is '{}')", action, status)));
+    }
+
+    Ok(deployment)
+}
+
+pub fn deployment_template(deployment: &Document) -> Option<TemplateVersion> {
+    mongodb::bson::from_bson(deployment.get("template_version")?.clone()).ok()
+}
+
+pub fn deployment_variables(deployment: &Document) -> Option<Map<String, Value>> {
+    mongodb::bson::from_document(deployment.get_document("variables").ok()?.clone()).ok()
+}
+
+// Versions are free-form labels, so the publish time decides whether the target moves the deployment forward
+fn is_older_version(target: &TemplateVersion, current: &TemplateVersion) -> bool {
+    target.version != current.version && target.published_at <= current.published_at
+}
+
+pub async fn plan_upgrade(store: web::Data<dyn ArtifactStore>, keyring: web::Data<Keyring>, path: web::Path<String>, request: web::Json<UpgradePlanRequest>) -> impl Responder {
+    let project_id = path.into_inner();
+    println!("📥 Received upgrade plan request for {}: {:?}", project_id, request);
+
+    let mongo_client = init_mongo_client().await;
+    let deployment = match find_running_deployment(mongo_client.clone(), &request.user_email, &project_id, "upgraded").await {
+        Ok(deployment) => deployment,
+        Err(resp) => return resp,
+    };
+
+    let (Some(current), Some(current_variables)) = (deployment_template(&deployment), deployment_variables(&deployment)) else {
+        return error_response(HttpResponse::Conflict(), "This deployment predates versioned templates and cannot be upgraded");
+    };
+
+    let credential = match CredentialRef::for_deployment(mongo_client.clone(), &deployment).await {
+        Ok(credential) => credential,
+        Err(e) => return error_response(HttpResponse::BadRequest(), format!("{}. Cannot plan the upgrade.", e)),
+    };
+
+    let (target, manifest) = match resolve_template_version(mongo_client.clone(), store.get_ref(), &current.name, request.template_version.as_deref()).await {
+        Ok(resolved) => resolved,
+        Err(resp) => return resp,
+    };
+
+    if target.hash == current.hash && request.variables.is_empty() {
+        return error_response(HttpResponse::Conflict(), format!("Deployment is already on {} version {}", current.name, current.version));
+    }
+    if is_older_version(&target, &current) {
+        return error_response(
+            HttpResponse::Conflict(),
+            format!("Version '{}' is not newer than the deployment's version '{}'", target.version, current.version),
+        );
+    }
+
+    if let Err(e) = verify_template_version(store.get_ref(), &target).await {
+        eprintln!("❌ Template integrity error: {}", e);
+        return error_response(HttpResponse::InternalServerError(), e.to_string());
+    }
+
+    let mut variables = current_variables;
+    variables.extend(request.variables.clone());
+    let user_data = deployment_user_data(&deployment);
+    let variables = match validate_variables(&manifest, &variables, &secret_variable_names(credential.provider, user_data.is_some())) {
+        Ok(variables) => variables,
+        Err(err_msg) => {
+            println!("❌ Variable validation failed: {}", err_msg);
+            return error_response(HttpResponse::BadRequest(), err_msg);
+        }
+    };
+
+    let secrets = match credential.secrets(mongo_client.clone(), &keyring).await.and_then(|mut secrets| {
+        add_user_data(&mut secrets, &keyring, &project_id, user_data.as_ref())?;
+        Ok(secrets)
+    }) {
+        Ok(secrets) => secrets,
+        Err(e) => return error_response(HttpResponse::BadRequest(), format!("{}. Cannot plan the upgrade.", e)),
+    };
+
+    // Planning reads the live state, so it must not overlap a running operation
+    let lock = match OperationLock::acquire(mongo_client.clone(), &project_id, "upgrade plan").await {
+        Ok(lock) => lock,
+        Err(e @ LockError::Held { .. }) => return error_response(HttpResponse::Conflict(), e.to_string()),
+        Err(e) => {
+            eprintln!("❌ Cannot lock {}: {}", project_id, e);
+            return error_response(HttpResponse::InternalServerError(), "Failed to lock deployment");
+        }
+    };
+
+    let project_name = deployment.get_str("project_name").unwrap_or("deployment");
+    let plan_id = Uuid::new_v4().to_string();
+    let local_dir = match create_project_temp_folder(project_name, &plan_id) {
+        Ok(dir) => dir,
+        Err(e) => {
+            lock.release().await;
+            eprintln!("❌ Failed to create plan folder: {}", e);
+            return error_response(HttpResponse::InternalServerError(), "Failed to prepare a working folder for the plan");
+        }
+    };
+
+    let source_prefix = template_version_prefix(&target.name, &target.version);
+    let replacements = placeholder_replacements(&manifest, &variables);
+
+    let rendered = async {
+        let (_, unused_placeholders) = render_template_to_local_dir(store.get_ref(), &source_prefix, &local_dir, &manifest.templated_files, &replacements).await?;
+        async_fs::write(local_dir.join("backend.tf"), store.terraform_backend(&project_id)).await?;
+        store.prepare_terraform_state(&project_id).await?;
+        write_tfvars(&local_dir, &variables).await?;
+        Ok::<_, Box<dyn std::error::Error + Send + Sync>>(unused_placeholders)
+    }
+    .await;
+
+    let response = match rendered {
+        Ok(unused_placeholders) => {
+            let mut output = RunLog::detached();
+            match run_terraform_plan_commands(&local_dir, &secrets, &mut output).await {
+                Ok(plan) => match store_pending_upgrade(mongo_client, &project_id, &target, &variables, &plan_fingerprint(&plan)).await {
+                    Ok(()) => {
+                        let (resource_changes, summary) = summarize_resource_changes(&plan);
+                        HttpResponse::Ok().json(ApiResponse {
+                            status: "success".into(),
+                            message: format!("Upgrade to {} version {} planned, nothing was applied", target.name, target.version),
+                            returneddata: Some(json!({
+                                "current_version": current,
+                                "target_version": target,
+                                "summary": summary,
+                                "resource_changes": resource_changes,
+                                "unused_placeholders": unused_placeholders,
+                            })),
+                        })
+                    }
+                    Err(e) => {
+                        eprintln!("❌ Failed to store pending upgrade for {}: {}", project_id, e);
+                        error_response(HttpResponse::InternalServerError(), "Failed to save the upgrade plan")
+                    }
+                },
+                Err(e) => {
+                    eprintln!("❌ Terraform plan failed: {}", e);
+                    HttpResponse::UnprocessableEntity().json(ApiResponse {
+                        status: "error".into(),
+                        message: format!("Terraform plan failed: {}", e),
+                        returneddata: terraform_error_details(e.as_ref()),
+                    })
+                }
+            }
+        }
+        Err(e) => {
+            eprintln!("❌ Template render error: {}", e);
+            error_response(HttpResponse::InternalServerError(), format!("Failed to render templates for the plan: {}", e))
+        }
+    };
+
+    lock.release().await;
+    if let Err(e) = async_fs::remove_dir_all(&local_dir).await {
+        eprintln!("⚠️ Failed to delete plan folder {}: {}", local_dir.display(), e);
+    }
+
+    response
+}
+
+pub async fn apply_upgrade(job_queue: web::Data<JobQueue>, path: web::Path<String>, request: web::Json<UpgradeApplyRequest>) -> impl Responder {
+    let project_id = path.into_inner();
+    println!("📥 Received upgrade request for {}: {:?}", project_id, request);
+
+    let mongo_client = init_mongo_client().await;
+    let deployment = match find_running_deployment(mongo_client.clone(), &request.user_email, &project_id, "upgraded").await {
+        Ok(deployment) => deployment,
+        Err(resp) => return resp,
+    };
+
+    // The upgrade applies exactly what was last planned, so the plan has to exist and match
+    let pending = deployment.get_document("pending_upgrade").ok();
+    let target: Option<TemplateVersion> = pending
+        .and_then(|pending| pending.get("template_version").cloned())
+        .and_then(|template| mongodb::bson::from_bson(template).ok());
+    let variables: Option<Map<String, Value>> = pending
+        .and_then(|pending| pending.get_document("variables").ok().cloned())
+        .and_then(|variables| mongodb::bson::from_document(variables).ok());
+
+    let reviewed_plan = pending.and_then(|pending| pending.get_str("plan_fingerprint").ok()).map(String::from);
+
+    let (Some(target), Some(variables)) = (target, variables) else {
+        return error_response(HttpResponse::Conflict(), "No upgrade has been planned for this deployment; plan it first");
+    };
+    let Some(reviewed_plan) = reviewed_plan else {
+        return error_response(HttpResponse::Conflict(), "The upgrade plan on record cannot be checked before applying; plan it again");
+    };
+    if target.version != request.template_version {
+        return error_response(
+            HttpResponse::Conflict(),
+            format!("The last plan was for version '{}'; plan version '{}' before applying it", target.version, request.template_version),
+        );
+    }
+    // The pinned version may have moved on since the plan was made
+    if let Some(current) = deployment_template(&deployment) {
+        if is_older_version(&target, &current) {
+            return error_response(
+                HttpResponse::Conflict(),
+                format!("Version '{}' is not newer than the deployment's version '{}'", target.version, current.version),
+            );
+        }
+    }
+
+    let credential = match CredentialRef::for_deployment(mongo_client.clone(), &deployment).await {
+        Ok(credential) => credential,
+        Err(e) => return error_response(HttpResponse::BadRequest(), format!("{}. Cannot apply the upgrade.", e)),
+    };
+
+    let job_id = Uuid::new_v4().to_string();
+    if let Err(e) = create_job(mongo_client.clone(), &job_id, &project_id, "upgrade", &request.user_email).await {
+        if is_duplicate_key(&e) {
+            return error_response(HttpResponse::Conflict(), "Another change to this deployment is queued or running, try again once it finishes");
+        }
+        eprintln!("❌ Failed to create upgrade job: {}", e);
+        return error_response(HttpResponse::InternalServerError(), "Failed to create upgrade job");
+    }
+
+    let job = ReapplyJob {
+        job_id: job_id.clone(),
+        project_id: project_id.clone(),
+        project_name: deployment.get_str("project_name").unwrap_or_default().to_string(),
+        operation: "upgrade".into(),
+        credential,
+        template: target,
+        variables,
+        user_data: deployment_user_data(&deployment),
+        reviewed_plan: Some(reviewed_plan),
+    };
+
+    if let Err(e) = job_queue.enqueue(Job::Reapply(Box::new(job))) {
+        eprintln!("❌ Failed to queue upgrade job {}: {}", job_id, e);
+        if let Err(e) = update_job_status(mongo_client, &job_id, "failed", &e, None).await {
+            eprintln!("❌ Failed to mark job {} as failed: {}", job_id, e);
+        }
+        return error_response(HttpResponse::ServiceUnavailable(), e);
+    }
+
+    HttpResponse::Accepted().json(ApiResponse {
+        status: "success".into(),
+        message: "Upgrade queued".into(),
+        returneddata: Some(json!({ "job_id": job_id, "project_id": project_id })),
+    })
+}
@@ -0,0 +1,181 @@
+use std::sync::Arc;
+use mongodb::{bson::oid::ObjectId, Client};
+use serde_json::json;
+use tokio::sync::{mpsc, Mutex};
+
+use crate::{
+    app_config::AppConfig,
+    utils::database::db::update_job_status,
+    utils::deployment::deploy::{fail_deployment, run_deploy_job, DeploymentRequest},
+    utils::deployment::reapply::run_reapply_job,
+    utils::settings::credentials::CredentialRef,
+    utils::settings::keyring::{Keyring, SealedSecret},
+    utils::storage::artifact_store::ArtifactStore,
+    utils::templates::versions::TemplateVersion,
+    utils::terraform::log_stream::LogHub,
+};
+
+// Maximum number of jobs waiting for a free worker before POST /deploy starts refusing work
+const QUEUE_CAPACITY: usize = 100;
+
+pub struct DeployJob {
+    pub job_id: String,
+    pub project_id: String,
+    pub user_id: ObjectId,
+    pub request: DeploymentRequest,
+    // The user's credential for the request's provider; the worker opens it just before Terraform runs
+    pub credential: CredentialRef,
+    pub template: TemplateVersion,
+    // Non-sensitive template variables, already validated against the manifest
+    pub variables: serde_json::Map<String, serde_json::Value>,
+    // The app's cloud-init, opened next to the credential
+    pub user_data: Option<SealedSecret>,
+}
+
+// Re-renders an existing deployment from a template version and variables, then applies it in place
+pub struct ReapplyJob {
+    pub job_id: String,
+    pub project_id: String,
+    pub project_name: String,
+    // What the reapply is for, e.g. "upgrade"; used for the lock, logs and status reasons
+    pub operation: String,
+    pub credential: CredentialRef,
+    pub template: TemplateVersion,
+    pub variables: serde_json::Map<String, serde_json::Value>,
+    pub user_data: Option<SealedSecret>,
+    // Fingerprint of the plan the user reviewed; when set, the apply is refused if Terraform now plans anything else
+    pub reviewed_plan: Option<String>,
+}
+
+pub enum Job {
+    Deploy(Box<DeployJob>),
+    Reapply(Box<ReapplyJob>),
+}
+
+impl Job {
+    pub fn job_id(&self) -> &str {
+        match self {
+            Job::Deploy(job) => &job.job_id,
+            Job::Reapply(job) => &job.job_id,
+        }
+    }
+
+    pub fn project_id(&self) -> &str {
+        match self {
+            Job::Deploy(job) => &job.project_id,
+            Job::Reapply(job) => &job.project_id,
+        }
+    }
+}
+
+// Why a job failed, plus any structured details (e.g. Terraform diagnostics) for clients polling the job
+pub struct JobFailure {
+    pub message: String,
+    pub details: Option<serde_json::Value>,
+}
+
+impl From<String> for JobFailure {
+    fn from(message: String) -> JobFailure {
+        JobFailure { message, details: None }
+    }
+}
+
+#[derive(Clone)]
+pub struct JobQueue {
+    sender: mpsc::Sender<Job>,
+}
+
+impl JobQueue {
+    // Spawns `workers` tasks that pull deploy jobs off a shared channel and run them one at a time
+    pub fn start(workers: usize, app_config: AppConfig, mongo_client: Client, store: Arc<dyn ArtifactStore>, keyring: Arc<Keyring>, log_hub: LogHub) -> JobQueue {
+        let (sender, receiver) = mpsc::channel::<Job>(QUEUE_CAPACITY);
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        for worker_id in 0..workers.max(1) {
+            let receiver = receiver.clone();
+            let app_config = app_config.clone();
+            let mongo_client = mongo_client.clone();
+            let store = store.clone();
+            let keyring = keyring.clone();
+            let log_hub = log_hub.clone();
+
+            tokio::spawn(async move {
+                loop {
+                    // Hold the lock only while waiting for the next job, not while running it
+                    let job = receiver.lock().await.recv().await;
+                    let Some(job) = job else {
+                        break;
+                    };
+
+                    println!("👷 Worker {} picked up job {} (project_id: {})", worker_id, job.job_id(), job.project_id());
+                    println!("--------------------------------------------------------");
+
+                    // Each job gets its own task, so a panic fails that job instead of taking the worker down with it
+                    let (job_id, project_id) = (job.job_id().to_string(), job.project_id().to_string());
+                    let running = tokio::spawn({
+                        let app_config = app_config.clone();
+                        let mongo_client = mongo_client.clone();
+                        let store = store.clone();
+                        let keyring = keyring.clone();
+                        let log_hub = log_hub.clone();
+                        async move { run_job(&app_config, mongo_client, store.as_ref(), &keyring, &log_hub, job).await }
+                    });
+
+                    if let Err(e) = running.await {
+                        eprintln!("❌ Job {} panicked on worker {}: {}", job_id, worker_id, e);
+                        if let Err(e) = update_job_status(mongo_client.clone(), &job_id, "failed", "The job stopped unexpectedly", None).await {
+                            eprintln!("❌ Failed to mark job {} as failed: {}", job_id, e);
+                        }
+                        fail_deployment(mongo_client.clone(), &project_id, "The job stopped unexpectedly").await;
+                    }
+                }
+            });
+        }
+
+        println!("👷 Started {} deployment worker(s)", workers.max(1));
+        println!("----------------------------------------");
+
+        JobQueue { sender }
+    }
+
+    pub fn enqueue(&self, job: Job) -> Result<(), String> {
+        self.sender.try_send(job).map_err(|e| match e {
+            mpsc::error::TrySendError::Full(_) => "Deployment queue is full, try again later".to_string(),
+            mpsc::error::TrySendError::Closed(_) => "Deployment workers are not running".to_string(),
+        })
+    }
+}
+
+async fn run_job(app_config: &AppConfig, mongo_client: Client, store: &dyn ArtifactStore, keyring: &Keyring, log_hub: &LogHub, job: Job) {
+    let job_id = job.job_id().to_string();
+
+    if let Err(e) = update_job_status(mongo_client.clone(), &job_id, "running", "Deployment in progress", None).await {
+        eprintln!("❌ Failed to mark job {} as running: {}", job_id, e);
+    }
+
+    let result = match job {
+        Job::Deploy(job) => run_deploy_job(app_config, mongo_client.clone(), store, keyring, log_hub, *job)
+            .await
+            .map(|unused| ("✅ Deployment initialized and executed".to_string(), unused)),
+        Job::Reapply(job) => {
+            let operation = job.operation.clone();
+            run_reapply_job(mongo_client.clone(), store, keyring, log_hub, *job)
+                .await
+                .map(|unused| (format!("✅ Deployment {} applied", operation), unused))
+        }
+    };
+
+    let (status, message, details) = match result {
+        // A placeholder the manifest declares but no file uses is a template bug worth showing, not a failure
+        Ok((message, unused_placeholders)) if !unused_placeholders.is_empty() => ("succeeded", message, Some(json!({ "unused_placeholders": unused_placeholders }))),
+        Ok((message, _)) => ("succeeded", message, None),
+        Err(failure) => {
+            eprintln!("❌ Job {} failed: {}", job_id, failure.message);
+            ("failed", failure.message, failure.details)
+        }
+    };
+
+    if let Err(e) = update_job_status(mongo_client, &job_id, status, &message, details.as_ref()).await {
+        eprintln!("❌ Failed to mark job {} as {}: {}", job_id, status, e);
+    }
+}
@@ -0,0 +1,43 @@
+use actix_web::{web, HttpResponse, Responder};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::deploy::ApiResponse;
+use crate::utils::database::db::find_job_by_id;
+use crate::utils::user::signup_func::init_mongo_client;
+
+#[derive(Deserialize)]
+pub struct JobStatusQuery {
+    pub user_email: String,
+}
+
+pub async fn get_job_status(path: web::Path<String>, query: web::Query<JobStatusQuery>) -> impl Responder {
+    let job_id = path.into_inner();
+    let mongo_client = init_mongo_client().await;
+
+    match find_job_by_id(mongo_client, &job_id).await {
+        // Someone else's job looks the same as a missing one
+        Ok(Some(mut job)) if job.get_str("user_email") == Ok(query.user_email.as_str()) => {
+            job.remove("_id");
+
+            HttpResponse::Ok().json(ApiResponse {
+                status: "success".into(),
+                message: format!("Job is {}", job.get_str("status").unwrap_or("unknown")),
+                returneddata: Some(json!(job)),
+            })
+        }
+        Ok(_) => HttpResponse::NotFound().json(ApiResponse {
+            status: "error".into(),
+            message: format!("Job '{}' not found", job_id),
+            returneddata: None,
+        }),
+        Err(e) => {
+            eprintln!("❌ Error fetching job {}: {}", job_id, e);
+            HttpResponse::InternalServerError().json(ApiResponse {
+                status: "error".into(),
+                message: "Failed to fetch job status".into(),
+                returneddata: None,
+            })
+        }
+    }
+}
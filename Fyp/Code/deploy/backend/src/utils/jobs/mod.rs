@@ -0,0 +1,2 @@
+pub mod job_queue;
+pub mod job_status;
\ No newline at end of file
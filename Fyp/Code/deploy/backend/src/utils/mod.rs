@@ -3,4 +3,9 @@ pub mod database;
 pub mod deployment;
 pub mod s3_bucket_handler;
 pub mod settings;
-pub mod terraform;
\ No newline at end of file
+pub mod terraform;
+pub mod jobs;
+pub mod templates;
+pub mod storage;
+pub mod apps;
+pub mod providers;
\ No newline at end of file
@@ -0,0 +1,135 @@
+use serde_json::{json, Map, Value};
+
+use crate::{
+    deploy::DeploymentRequest,
+    utils::providers::cloud_provider::{CloudProvider, Credential, ServerType},
+    utils::terraform::variables::TerraformSecrets,
+};
+
+const REGIONS: [&str; 15] = [
+    "us-east-1",
+    "us-east-2",
+    "us-west-1",
+    "us-west-2",
+    "ca-central-1",
+    "sa-east-1",
+    "eu-west-1",
+    "eu-west-2",
+    "eu-west-3",
+    "eu-central-1",
+    "eu-north-1",
+    "ap-south-1",
+    "ap-southeast-1",
+    "ap-southeast-2",
+    "ap-northeast-1",
+];
+
+// x86 types only, since the template boots the amd64 Debian image
+const SERVER_TYPES: [ServerType; 9] = [
+    ServerType { name: "t3.micro", memory_mb: 1024, size: None },
+    ServerType { name: "t3.small", memory_mb: 2048, size: Some("small") },
+    ServerType { name: "t3.medium", memory_mb: 4096, size: Some("medium") },
+    ServerType { name: "t3.large", memory_mb: 8192, size: Some("large") },
+    ServerType { name: "t3.xlarge", memory_mb: 16384, size: None },
+    ServerType { name: "t3.2xlarge", memory_mb: 32768, size: None },
+    ServerType { name: "m6i.large", memory_mb: 8192, size: None },
+    ServerType { name: "m6i.xlarge", memory_mb: 16384, size: None },
+    ServerType { name: "m6i.2xlarge", memory_mb: 32768, size: None },
+];
+
+pub struct Aws;
+
+impl CloudProvider for Aws {
+    fn id(&self) -> &'static str {
+        "aws"
+    }
+
+    fn name(&self) -> &'static str {
+        "Amazon Web Services"
+    }
+
+    fn regions(&self) -> &'static [&'static str] {
+        &REGIONS
+    }
+
+    fn server_types(&self) -> &'static [ServerType] {
+        &SERVER_TYPES
+    }
+
+    fn default_template(&self) -> &'static str {
+        "aws"
+    }
+
+    // Stored as a JSON object with both fields
+    fn credential_fields(&self) -> &'static [&'static str] {
+        &["access_key_id", "secret_access_key"]
+    }
+
+    fn secret_variables(&self) -> &'static [&'static str] {
+        &["aws_access_key_id", "aws_secret_access_key"]
+    }
+
+    fn secrets(&self, credential: &Credential) -> TerraformSecrets {
+        TerraformSecrets::from([
+            ("aws_access_key_id".to_string(), credential["access_key_id"].clone()),
+            ("aws_secret_access_key".to_string(), credential["secret_access_key"].clone()),
+        ])
+    }
+
+    fn server_variables(&self, request: &DeploymentRequest, server_type: &ServerType) -> Map<String, Value> {
+        let mut variables = Map::new();
+        variables.insert("node-name".into(), json!(format!("{}-server", request.project_name)));
+        variables.insert("instance_type".into(), json!(server_type.name));
+        variables.insert("region".into(), json!(request.region));
+        variables.insert("volume_size".into(), json!(request.volume_size));
+        // A dynamic IP changes whenever the instance stops, so only "reserved" gets an Elastic IP
+        variables.insert("reserved_ip".into(), json!(request.ip_option == "reserved"));
+        variables
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use serde_json::{json, Value};
+
+    use super::Aws;
+    use crate::{
+        deploy::{build_variables, DeploymentRequest},
+        utils::deployment::cloud_init::secret_variable_names,
+        utils::templates::catalog::TemplateManifest,
+        utils::terraform::variables::{render_tfvars, validate_variables},
+    };
+
+    // tests/plan.tftest.hcl plans against this file, so the Terraform test sees exactly what the backend writes
+    const AWS_TEMPLATE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../deployment/terraform/aws");
+
+    #[test]
+    fn generated_tfvars_match_the_plan_test_fixture() {
+        let manifest: TemplateManifest = serde_json::from_slice(&std::fs::read(Path::new(AWS_TEMPLATE).join("manifest.json")).unwrap()).unwrap();
+        let request: DeploymentRequest = serde_json::from_value(json!({
+            "project_name": "demo",
+            "selected_service": "aws",
+            "selected_server": "t3.small",
+            "region": "eu-central-1",
+            "volume_size": 20,
+            "ip_option": "reserved",
+            "ssh_key_option": null,
+            "ssh_key": null,
+            "user_email": "demo@example.com",
+        }))
+        .unwrap();
+
+        let variables = build_variables(&Aws, &request, Default::default()).unwrap();
+        let variables = validate_variables(&manifest, &variables, &secret_variable_names(&Aws, false)).unwrap();
+        let tfvars = render_tfvars(&variables).unwrap();
+
+        let fixture: Value = serde_json::from_slice(&std::fs::read(Path::new(AWS_TEMPLATE).join("tests/backend.tfvars.json")).unwrap()).unwrap();
+        assert_eq!(
+            serde_json::from_slice::<Value>(&tfvars).unwrap(),
+            fixture,
+            "tests/backend.tfvars.json is out of date, replace it with:\n{}",
+            String::from_utf8_lossy(&tfvars)
+        );
+    }
+}
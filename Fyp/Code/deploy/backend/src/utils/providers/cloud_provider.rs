@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+use actix_web::{HttpResponse, Responder};
+use mongodb::bson::Document;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+use crate::{
+    deploy::{ApiResponse, DeploymentRequest},
+    utils::providers::aws::Aws,
+    utils::providers::digitalocean::DigitalOcean,
+    utils::providers::hetzner::Hetzner,
+    utils::terraform::variables::TerraformSecrets,
+};
+
+// Provider-neutral sizes clients can ask for; each provider maps them onto one of its own server types
+pub const SERVER_SIZES: [&str; 3] = ["small", "medium", "large"];
+
+// Deployments recorded before providers existed all ran the Hetzner template
+pub const LEGACY_PROVIDER: &str = "hetzner";
+
+static PROVIDERS: [&dyn CloudProvider; 3] = [&Hetzner, &Aws, &DigitalOcean];
+
+// Credential field → value, e.g. "token" for Hetzner
+pub type Credential = HashMap<String, String>;
+
+#[derive(Serialize, Debug)]
+pub struct ServerType {
+    pub name: &'static str,
+    pub memory_mb: u32,
+    // The entry of SERVER_SIZES this type stands for, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<&'static str>,
+}
+
+// Everything that differs between clouds: where servers can go, what they are called,
+// what a credential looks like and how a request turns into the template's variables
+pub trait CloudProvider: Send + Sync {
+    // Lowercase id, matched against `DeploymentRequest.selected_service` and a template manifest's `provider`
+    fn id(&self) -> &'static str;
+
+    fn name(&self) -> &'static str;
+
+    fn regions(&self) -> &'static [&'static str];
+
+    fn server_types(&self) -> &'static [ServerType];
+
+    // Template used when a request does not name one
+    fn default_template(&self) -> &'static str;
+
+    fn credential_fields(&self) -> &'static [&'static str];
+
+    // Names of the template's sensitive variables that `secrets` fills in
+    fn secret_variables(&self) -> &'static [&'static str];
+
+    // Maps a credential onto the template's sensitive variables
+    fn secrets(&self, credential: &Credential) -> TerraformSecrets;
+
+    // Variables describing the server itself, e.g. its name, type and region
+    fn server_variables(&self, request: &DeploymentRequest, server_type: &ServerType) -> Map<String, Value>;
+
+    // Protocols the provider's firewall can open to inbound traffic
+    fn firewall_protocols(&self) -> &'static [&'static str] {
+        &["tcp", "udp", "icmp", "gre", "esp"]
+    }
+
+    // Accepts either a server type name or one of SERVER_SIZES
+    fn server_type(&self, selected: &str) -> Option<&'static ServerType> {
+        self.server_types()
+            .iter()
+            .find(|server_type| server_type.name.eq_ignore_ascii_case(selected) || server_type.size == Some(selected))
+    }
+
+    // Every field present and non-empty, and nothing else
+    fn validate_credential(&self, credential: &Credential) -> Result<(), String> {
+        let fields = self.credential_fields();
+        if let Some(field) = fields.iter().find(|field| credential.get(**field).is_none_or(|value| value.trim().is_empty())) {
+            return Err(format!("'{}' is missing", field));
+        }
+        if let Some(field) = credential.keys().find(|field| !fields.contains(&field.as_str())) {
+            return Err(format!("unknown field '{}'", field));
+        }
+        Ok(())
+    }
+
+    // A single-field credential is the bare value; anything else is a JSON object of its fields
+    fn parse_credential(&self, raw: &str) -> Result<Credential, String> {
+        let fields = self.credential_fields();
+        let credential: Credential = match fields {
+            [field] => Credential::from([(field.to_string(), raw.trim().to_string())]),
+            _ => serde_json::from_str(raw).map_err(|_| format!("expected a JSON object with {}", fields.join(", ")))?,
+        };
+
+        self.validate_credential(&credential)?;
+        Ok(credential)
+    }
+}
+
+pub fn find_provider(id: &str) -> Option<&'static dyn CloudProvider> {
+    PROVIDERS.iter().copied().find(|provider| provider.id().eq_ignore_ascii_case(id))
+}
+
+pub fn deployment_provider(deployment: &Document) -> Result<&'static dyn CloudProvider, String> {
+    let id = deployment.get_str("provider").unwrap_or(LEGACY_PROVIDER);
+    find_provider(id).ok_or_else(|| format!("Deployment uses unknown provider '{}'", id))
+}
+
+pub async fn list_providers() -> impl Responder {
+    let providers: Vec<Value> = PROVIDERS
+        .iter()
+        .map(|provider| {
+            json!({
+                "id": provider.id(),
+                "name": provider.name(),
+                "regions": provider.regions(),
+                "server_types": provider.server_types(),
+                "credential_fields": provider.credential_fields(),
+                "default_template": provider.default_template(),
+            })
+        })
+        .collect();
+
+    HttpResponse::Ok().json(ApiResponse {
+        status: "success".into(),
+        message: "Providers fetched successfully".into(),
+        returneddata: Some(json!({ "providers": providers })),
+    })
+}
@@ -0,0 +1,73 @@
+use serde_json::{json, Map, Value};
+
+use crate::{
+    deploy::DeploymentRequest,
+    utils::providers::cloud_provider::{CloudProvider, Credential, ServerType},
+    utils::terraform::variables::TerraformSecrets,
+};
+
+// Regions that offer both block storage and reserved IPs
+const REGIONS: [&str; 11] = ["nyc1", "nyc3", "sfo2", "sfo3", "tor1", "ams3", "lon1", "fra1", "blr1", "sgp1", "syd1"];
+
+const SERVER_TYPES: [ServerType; 9] = [
+    ServerType { name: "s-1vcpu-1gb", memory_mb: 1024, size: None },
+    ServerType { name: "s-1vcpu-2gb", memory_mb: 2048, size: Some("small") },
+    ServerType { name: "s-2vcpu-2gb", memory_mb: 2048, size: None },
+    ServerType { name: "s-2vcpu-4gb", memory_mb: 4096, size: Some("medium") },
+    ServerType { name: "c-2", memory_mb: 4096, size: None },
+    ServerType { name: "s-4vcpu-8gb", memory_mb: 8192, size: Some("large") },
+    ServerType { name: "g-2vcpu-8gb", memory_mb: 8192, size: None },
+    ServerType { name: "c-4", memory_mb: 8192, size: None },
+    ServerType { name: "s-8vcpu-16gb", memory_mb: 16384, size: None },
+];
+
+pub struct DigitalOcean;
+
+impl CloudProvider for DigitalOcean {
+    fn id(&self) -> &'static str {
+        "digitalocean"
+    }
+
+    fn name(&self) -> &'static str {
+        "DigitalOcean"
+    }
+
+    fn regions(&self) -> &'static [&'static str] {
+        &REGIONS
+    }
+
+    fn server_types(&self) -> &'static [ServerType] {
+        &SERVER_TYPES
+    }
+
+    fn default_template(&self) -> &'static str {
+        "digitalocean"
+    }
+
+    fn credential_fields(&self) -> &'static [&'static str] {
+        &["token"]
+    }
+
+    fn secret_variables(&self) -> &'static [&'static str] {
+        &["do_token"]
+    }
+
+    fn secrets(&self, credential: &Credential) -> TerraformSecrets {
+        TerraformSecrets::from([("do_token".to_string(), credential["token"].clone())])
+    }
+
+    fn server_variables(&self, request: &DeploymentRequest, server_type: &ServerType) -> Map<String, Value> {
+        let mut variables = Map::new();
+        variables.insert("node-name".into(), json!(format!("{}-server", request.project_name)));
+        variables.insert("size".into(), json!(server_type.name));
+        variables.insert("region".into(), json!(request.region));
+        variables.insert("volume_size".into(), json!(request.volume_size));
+        variables.insert("reserved_ip".into(), json!(request.ip_option == "reserved"));
+        variables
+    }
+
+    // Cloud Firewalls have no GRE or ESP rules
+    fn firewall_protocols(&self) -> &'static [&'static str] {
+        &["tcp", "udp", "icmp"]
+    }
+}
@@ -0,0 +1,75 @@
+use serde_json::{json, Map, Value};
+
+use crate::{
+    deploy::DeploymentRequest,
+    utils::providers::cloud_provider::{CloudProvider, Credential, ServerType},
+    utils::terraform::variables::TerraformSecrets,
+};
+
+const REGIONS: [&str; 6] = ["fsn1", "nbg1", "hel1", "ash", "hil", "sin"];
+
+const SERVER_TYPES: [ServerType; 19] = [
+    ServerType { name: "cpx11", memory_mb: 2048, size: Some("small") },
+    ServerType { name: "cx22", memory_mb: 4096, size: Some("medium") },
+    ServerType { name: "cpx21", memory_mb: 4096, size: None },
+    ServerType { name: "cax11", memory_mb: 4096, size: None },
+    ServerType { name: "cx32", memory_mb: 8192, size: Some("large") },
+    ServerType { name: "cpx31", memory_mb: 8192, size: None },
+    ServerType { name: "cax21", memory_mb: 8192, size: None },
+    ServerType { name: "ccx13", memory_mb: 8192, size: None },
+    ServerType { name: "cx42", memory_mb: 16384, size: None },
+    ServerType { name: "cpx41", memory_mb: 16384, size: None },
+    ServerType { name: "cax31", memory_mb: 16384, size: None },
+    ServerType { name: "ccx23", memory_mb: 16384, size: None },
+    ServerType { name: "cx52", memory_mb: 32768, size: None },
+    ServerType { name: "cpx51", memory_mb: 32768, size: None },
+    ServerType { name: "cax41", memory_mb: 32768, size: None },
+    ServerType { name: "ccx33", memory_mb: 32768, size: None },
+    ServerType { name: "ccx43", memory_mb: 65536, size: None },
+    ServerType { name: "ccx53", memory_mb: 131072, size: None },
+    ServerType { name: "ccx63", memory_mb: 196608, size: None },
+];
+
+pub struct Hetzner;
+
+impl CloudProvider for Hetzner {
+    fn id(&self) -> &'static str {
+        "hetzner"
+    }
+
+    fn name(&self) -> &'static str {
+        "Hetzner Cloud"
+    }
+
+    fn regions(&self) -> &'static [&'static str] {
+        &REGIONS
+    }
+
+    fn server_types(&self) -> &'static [ServerType] {
+        &SERVER_TYPES
+    }
+
+    fn default_template(&self) -> &'static str {
+        "hetzner"
+    }
+
+    fn credential_fields(&self) -> &'static [&'static str] {
+        &["token"]
+    }
+
+    fn secret_variables(&self) -> &'static [&'static str] {
+        &["hcloud_token"]
+    }
+
+    fn secrets(&self, credential: &Credential) -> TerraformSecrets {
+        TerraformSecrets::from([("hcloud_token".to_string(), credential["token"].clone())])
+    }
+
+    fn server_variables(&self, request: &DeploymentRequest, server_type: &ServerType) -> Map<String, Value> {
+        let mut variables = Map::new();
+        variables.insert("node-name".into(), json!(format!("{}-server", request.project_name)));
+        variables.insert("size".into(), json!(server_type.name));
+        variables.insert("location".into(), json!(request.region));
+        variables
+    }
+}
@@ -0,0 +1,4 @@
+pub mod cloud_provider;
+pub mod hetzner;
+pub mod aws;
+pub mod digitalocean;
\ No newline at end of file
@@ -1,128 +1,176 @@
 // s3_handler.rs
-use aws_sdk_s3::types::{Delete, ObjectIdentifier};
-use aws_sdk_s3::Client;
-use std::collections::HashMap;
-use tokio::io::AsyncReadExt;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
 
-pub async fn copy_and_transform_files(aws_client: &Client,s3_bucket: &str,source_prefix: &str,destination_prefix: &str,replacements: &HashMap<String, String>,distro: Option<&str>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    println!("📦 Starting copy from s3://{}/{}", s3_bucket, source_prefix);
-    println!("--------------------------------------------------------");
+use crate::utils::storage::artifact_store::ArtifactStore;
+use crate::utils::terraform::variables::escape_hcl_string;
+
+// Builds the matcher for a manifest's `templated_files`; `*` stays inside one path segment, `**/` spans folders
+pub fn templated_files_matcher(patterns: &[String]) -> Result<GlobSet, Box<dyn std::error::Error + Send + Sync>> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = GlobBuilder::new(pattern)
+            .literal_separator(true)
+            .build()
+            .map_err(|e| format!("Invalid templated_files pattern '{}': {}", pattern, e))?;
+        builder.add(glob);
+    }
+    Ok(builder.build()?)
+}
+
+// Replaces every placeholder in a single pass over the template, so the result does not depend on the map's order
+// and a value that happens to contain another placeholder's token is left as the user wrote it
+pub fn render_contents(contents: &str, replacements: &HashMap<String, String>) -> Result<String, regex::Error> {
+    if replacements.is_empty() {
+        return Ok(contents.to_string());
+    }
 
-    // Cloning replacements and adding distro (default "debian-12") to it
-    let mut effective_replacements = replacements.clone();
-    let distro_val = distro.unwrap_or("debian-12");
-    effective_replacements.insert("__DISTRO__".to_string(), distro_val.to_string());
+    // Longest first, so a placeholder that contains another one is matched whole
+    let mut placeholders: Vec<&String> = replacements.keys().collect();
+    placeholders.sort_by(|a, b| b.len().cmp(&a.len()).then(a.cmp(b)));
+    let pattern = placeholders.iter().map(|placeholder| regex::escape(placeholder)).collect::<Vec<_>>().join("|");
 
-    let objects = aws_client
-        .list_objects_v2()
-        .bucket(s3_bucket)
-        .prefix(source_prefix)
-        .send()
-        .await?;
+    let placeholder_re = Regex::new(&pattern)?;
+    Ok(placeholder_re
+        .replace_all(contents, |caps: &regex::Captures| replacements[&caps[0]].clone())
+        .into_owned())
+}
+
+// Refuses templates with `__UPPER_CASE__` tokens that no replacement covers, then substitutes the rest,
+// recording which replacement keys the file actually used. Tokens are looked for in the template, not the output,
+// so user values may contain them
+pub fn render_checked(file: &str, contents: &str, replacements: &HashMap<String, String>, used: &mut HashSet<String>) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let placeholder_re = Regex::new(r"__[A-Z0-9]+(?:_[A-Z0-9]+)*__")?;
+    let mut leftover: Vec<&str> = placeholder_re
+        .find_iter(contents)
+        .map(|m| m.as_str())
+        .filter(|token| !replacements.contains_key(*token))
+        .collect();
+    leftover.sort();
+    leftover.dedup();
+
+    if !leftover.is_empty() {
+        return Err(format!("Unresolved placeholder(s) in {}: {}", file, leftover.join(", ")).into());
+    }
+
+    used.extend(replacements.keys().filter(|placeholder| contents.contains(placeholder.as_str())).cloned());
+
+    Ok(render_contents(contents, replacements)?)
+}
+
+// Placeholders the manifest declares that no templated file referenced, so the caller can report them
+fn unused_replacements(replacements: &HashMap<String, String>, used: &HashSet<String>) -> Vec<String> {
+    let mut unused: Vec<String> = replacements
+        .keys()
+        .filter(|placeholder| !used.contains(*placeholder))
+        .cloned()
+        .collect();
+
+    if !unused.is_empty() {
+        unused.sort();
+        println!("⚠️ Replacement(s) not used by any templated file: {}", unused.join(", "));
+        println!("--------------------------------------------------------");
+    }
+    unused
+}
+
+// Returns the declared placeholders no templated file used
+pub async fn copy_and_transform_files(store: &dyn ArtifactStore,source_prefix: &str,destination_prefix: &str,templated_files: &[String],replacements: &HashMap<String, String>) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+    println!("📦 Starting copy from {}", source_prefix);
+    println!("--------------------------------------------------------");
+
+    let templated = templated_files_matcher(templated_files)?;
+    let mut used = HashSet::new();
 
     println!("🔍 Listing objects...");
     println!("--------------------------------------------------------");
 
-    if let Some(contents) = objects.contents {
-        println!("✅ Found {} objects", contents.len());
+    let source_keys = store.list(source_prefix).await?;
+
+    if source_keys.is_empty() {
+        println!("⚠️ No objects found under prefix: {}", source_prefix);
+        println!("--------------------------------------------------------");
+    } else {
+        println!("✅ Found {} objects", source_keys.len());
         println!("--------------------------------------------------------");
+    }
 
-        for object in contents {
-            if let Some(source_key) = object.key() {
-                println!("➡️ Processing object: {}", source_key);
-                println!("--------------------------------------------------------");
+    for source_key in &source_keys {
+        println!("➡️ Processing object: {}", source_key);
+        println!("--------------------------------------------------------");
 
-                let relative_path = source_key.strip_prefix(source_prefix).unwrap_or(source_key);
-                let destination_key = format!("{}{}", destination_prefix, relative_path);
+        let relative_path = source_key.strip_prefix(source_prefix).unwrap_or(source_key);
+        let destination_key = format!("{}{}", destination_prefix, relative_path);
 
-                if relative_path == "variables.tf" {
-                    println!("✏️ Detected 'variables.tf', modifying before uploading...");
-                    println!("--------------------------------------------------------");
+        if templated.is_match(relative_path) {
+            println!("✏️ '{}' is templated, rendering before uploading...", relative_path);
+            println!("--------------------------------------------------------");
 
-                    match modify(
-                        &aws_client,
-                        &s3_bucket,
-                        &source_key,
-                        &effective_replacements,
-                    )
-                    .await
-                    {
-                        Ok(modified_bytes) => {
-                            println!("⬆️ Uploading modified 'variable.tf' to destination...");
-                            println!("--------------------------------------------------------");
-                            if let Err(e) = aws_client
-                                .put_object()
-                                .bucket(s3_bucket)
-                                .key(&destination_key)
-                                .body(modified_bytes.into())
-                                .send()
-                                .await
-                            {
-                                eprintln!("❌ Failed to upload modified 'variable.tf': {}", e);
-                                println!(
-                                    "--------------------------------------------------------"
-                                );
-                                return Err(Box::new(e));
-                            }
-                            println!("✅ Modified 'variable.tf' uploaded successfully.");
-                            println!("--------------------------------------------------------");
-                        }
-                        Err(e) => {
-                            eprintln!("❌ Failed to modify 'variable.tf': {}", e);
-                            println!("--------------------------------------------------------");
-                            return Err(e);
-                        }
+            match modify(store, source_key, replacements, &mut used).await {
+                Ok(modified_bytes) => {
+                    if let Err(e) = store.put(&destination_key, modified_bytes).await {
+                        eprintln!("❌ Failed to upload rendered '{}': {}", relative_path, e);
+                        println!("--------------------------------------------------------");
+                        return Err(e);
                     }
-                } else {
-                    let copy_source = format!("{}/{}", s3_bucket, source_key);
-                    println!("📁 From: {}", copy_source);
+                    println!("✅ Rendered '{}' uploaded successfully.", relative_path);
                     println!("--------------------------------------------------------");
-                    println!("📂 To:   {}", destination_key);
+                }
+                Err(e) => {
+                    eprintln!("❌ Failed to render '{}': {}", relative_path, e);
                     println!("--------------------------------------------------------");
-
-                    aws_client
-                        .copy_object()
-                        .bucket(s3_bucket)
-                        .copy_source(copy_source)
-                        .key(destination_key)
-                        .send()
-                        .await?;
+                    return Err(e);
                 }
             }
+        } else {
+            println!("📁 From: {}", source_key);
+            println!("--------------------------------------------------------");
+            println!("📂 To:   {}", destination_key);
+            println!("--------------------------------------------------------");
+
+            store.copy(source_key, &destination_key).await?;
         }
-    } else {
-        println!("⚠️ No objects found under prefix: {}", source_prefix);
-        println!("--------------------------------------------------------");
     }
 
-    Ok(())
+    Ok(unused_replacements(replacements, &used))
 }
 
-pub async fn modify(aws_client: &Client,s3_bucket: &str,source_key: &str,replacements: &HashMap<String, String>) -> Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>> {
-    println!("🛠️ Modifying file s3://{}/{}", s3_bucket, source_key);
+pub async fn modify(store: &dyn ArtifactStore,source_key: &str,replacements: &HashMap<String, String>,used: &mut HashSet<String>) -> Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>> {
+    println!("🛠️ Modifying file {}", source_key);
     println!("--------------------------------------------------------");
 
-    let object_output = aws_client
-        .get_object()
-        .bucket(s3_bucket)
-        .key(source_key)
-        .send()
-        .await?;
-
-    let mut body = object_output.body.into_async_read();
-    let mut contents = String::new();
-    body.read_to_string(&mut contents).await?;
+    let bytes = store.get(source_key).await?.ok_or_else(|| format!("{} not found", source_key))?;
     println!("✅ Downloaded file successfully");
     println!("--------------------------------------------------------");
 
-    let mut modified_contents = contents.clone();
-    for (placeholder, value) in replacements {
-        println!("🔁 Replacing {} => {}", placeholder, value);
-        println!();
-        modified_contents = modified_contents.replace(placeholder, value);
-        println!("📝 Modified variable.tf content:\n{}", modified_contents);
-        println!("--------------------------------------------------------");
+    // A glob like `files/**` can also match images or archives, which have no placeholders to fill
+    let contents = match String::from_utf8(bytes) {
+        Ok(contents) => contents,
+        Err(e) => {
+            println!("📎 {} is not UTF-8 text, copying it verbatim", source_key);
+            println!("--------------------------------------------------------");
+            return Ok(e.into_bytes());
+        }
+    };
+
+    // Placeholders in Terraform files sit inside quoted strings, so a value must not be able to break out of one
+    let escaped;
+    let replacements = if source_key.ends_with(".tf") {
+        escaped = replacements
+            .iter()
+            .map(|(placeholder, value)| (placeholder.clone(), escape_hcl_string(value)))
+            .collect::<HashMap<String, String>>();
+        &escaped
+    } else {
+        replacements
+    };
+
+    for placeholder in replacements.keys() {
+        println!("🔁 Replacing {}", placeholder);
     }
+    let modified_contents = render_checked(source_key, &contents, replacements, used)?;
     println!("✅ Applied modifications");
     println!("--------------------------------------------------------");
 
@@ -130,43 +178,116 @@ pub async fn modify(aws_client: &Client,s3_bucket: &str,source_key: &str,replace
 }
 
 
-pub async fn delete_specific_deployment_folder(aws_client: &Client,s3_bucket: &str,project_prefix: &str) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
-    println!("🔍 Attempting to delete folder: {}", project_prefix);
+// Renders a template into a local folder the same way copy_and_transform_files renders it into the store;
+// returns how many files were written and the declared placeholders none of them used
+pub async fn render_template_to_local_dir(store: &dyn ArtifactStore,source_prefix: &str,local_dir: &Path,templated_files: &[String],replacements: &HashMap<String, String>) -> Result<(usize, Vec<String>), Box<dyn std::error::Error + Send + Sync>> {
+    println!("📦 Rendering {} into {}", source_prefix, local_dir.display());
     println!("--------------------------------------------------------");
 
-    let folder_objects = aws_client
-        .list_objects_v2()
-        .bucket(s3_bucket)
-        .prefix(project_prefix)
-        .send()
-        .await?;
-
-    let to_delete: Vec<ObjectIdentifier> = folder_objects
-        .contents()
-        .iter()
-        .filter_map(|obj| {
-            obj.key().and_then(|k| {
-                println!("🗑 Deleting: {}", k);
-                println!("--------------------------------------------------------");
-                ObjectIdentifier::builder().key(k).build().ok()
-            })
-        })
+    let templated = templated_files_matcher(templated_files)?;
+    let mut used = HashSet::new();
+
+    let mut rendered = 0;
+    for source_key in store.list(source_prefix).await? {
+        let relative_path = source_key.strip_prefix(source_prefix).unwrap_or(&source_key);
+        if relative_path.is_empty() {
+            continue;
+        }
+
+        let contents = if templated.is_match(relative_path) {
+            modify(store, &source_key, replacements, &mut used).await?
+        } else {
+            store.get(&source_key).await?.ok_or_else(|| format!("{} not found", source_key))?
+        };
+
+        let local_path = local_dir.join(relative_path);
+        if let Some(parent) = local_path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(&local_path, contents).await?;
+        rendered += 1;
+    }
+
+    let unused = unused_replacements(replacements, &used);
+    println!("✅ Rendered {} template file(s)", rendered);
+    println!("--------------------------------------------------------");
+    Ok((rendered, unused))
+}
+
+
+// Makes `destination_prefix` hold exactly what is under `staging_prefix`: every staged key is copied over
+// its counterpart first, and only then are keys the new render no longer has removed. The staging copy is deleted last
+pub async fn swap_in_staged_folder(store: &dyn ArtifactStore,staging_prefix: &str,destination_prefix: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let staged: Vec<String> = store
+        .list(staging_prefix)
+        .await?
+        .into_iter()
+        .filter_map(|key| key.strip_prefix(staging_prefix).map(str::to_string))
+        .filter(|relative_path| !relative_path.is_empty())
         .collect();
+    if staged.is_empty() {
+        return Err(format!("Nothing was staged under {}", staging_prefix).into());
+    }
+
+    for relative_path in &staged {
+        store.copy(&format!("{}{}", staging_prefix, relative_path), &format!("{}{}", destination_prefix, relative_path)).await?;
+    }
 
-    if to_delete.is_empty() {
+    for key in store.list(destination_prefix).await? {
+        let relative_path = key.strip_prefix(destination_prefix).unwrap_or(&key);
+        if !staged.iter().any(|staged| staged == relative_path) {
+            println!("🧹 Removing stale {}", key);
+            store.delete(&key).await?;
+        }
+    }
+
+    store.delete_prefix(staging_prefix).await?;
+    Ok(())
+}
+
+pub async fn delete_specific_deployment_folder(store: &dyn ArtifactStore,project_prefix: &str) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    println!("🔍 Attempting to delete folder: {}", project_prefix);
+    println!("--------------------------------------------------------");
+
+    let deleted = store.delete_prefix(project_prefix).await?;
+
+    if deleted == 0 {
         println!("⚠ Folder is empty or no objects to delete.");
         println!("--------------------------------------------------------");
         return Ok(false);
     }
 
-    aws_client
-        .delete_objects()
-        .bucket(s3_bucket)
-        .delete(Delete::builder().set_objects(Some(to_delete)).build()?)
-        .send()
-        .await?;
-
-    println!("✅ Deleted S3 folder: {}", project_prefix);
+    println!("✅ Deleted {} object(s) under: {}", deleted, project_prefix);
     println!("--------------------------------------------------------");
     Ok(true)
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replacements(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(placeholder, value)| (placeholder.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn keeps_tokens_inside_values() {
+        let replacements = replacements(&[("__NAME__", "__REGION__ box"), ("__REGION__", "fra1")]);
+        let mut used = HashSet::new();
+
+        let rendered = render_checked("main.tf", "name = \"__NAME__\"\nregion = \"__REGION__\"", &replacements, &mut used).unwrap();
+
+        assert_eq!(rendered, "name = \"__REGION__ box\"\nregion = \"fra1\"");
+        assert_eq!(used.len(), 2);
+    }
+
+    #[test]
+    fn reports_tokens_without_a_replacement() {
+        let replacements = replacements(&[("__NAME__", "__SIZE__")]);
+        let mut used = HashSet::new();
+
+        let error = render_checked("main.tf", "__NAME__ __DISTRO__ __DISTRO__", &replacements, &mut used).unwrap_err();
+
+        assert_eq!(error.to_string(), "Unresolved placeholder(s) in main.tf: __DISTRO__");
+    }
+}
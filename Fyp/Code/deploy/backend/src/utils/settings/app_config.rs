@@ -1,4 +1,6 @@
 use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
 use std::fs;
 use std::env;
 use serde_json;
@@ -6,8 +8,80 @@ use serde_json;
 #[derive(Clone, Debug, Deserialize)]
 pub struct AppConfig {
     pub mongo_uri: String,
+    #[serde(default)]
     pub s3_bucket: String,
+    #[serde(default)]
     pub aws_region: String,
+    #[serde(default = "default_deploy_workers")]
+    pub deploy_workers: usize,
+    #[serde(default)]
+    pub rollback_policy: RollbackPolicy,
+    #[serde(default)]
+    pub storage: StorageConfig,
+    // Master keys for stored cloud credentials; CREDENTIAL_KEYS and CREDENTIAL_ACTIVE_KEY from the environment when left out
+    #[serde(default)]
+    pub credential_keys: Option<CredentialKeysConfig>,
+    // Emails of the users allowed to publish template versions; OPERATOR_EMAILS (comma-separated) when left out
+    #[serde(default)]
+    pub operators: Vec<String>,
+}
+
+// e.g. `{ "active": "2025-01", "keys": { "2024-06": "<base64 32 bytes>", "2025-01": "<base64 32 bytes>" } }`.
+// Rotate by adding a key and making it active; older ones are still needed until the next startup has resealed
+// every credential and cloud-init (see the README)
+#[derive(Clone, Deserialize)]
+pub struct CredentialKeysConfig {
+    pub active: String,
+    pub keys: HashMap<String, String>,
+}
+
+// Only the key ids, so the config can be logged
+impl fmt::Debug for CredentialKeysConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CredentialKeysConfig")
+            .field("active", &self.active)
+            .field("keys", &self.keys.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+// Where templates and deployment artifacts are kept, e.g. `{ "backend": "local", "root": "local-store", "deployment_dir": "../deployment" }`
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(tag = "backend", rename_all = "snake_case")]
+pub enum StorageConfig {
+    // The `s3_bucket` in `aws_region`
+    #[default]
+    S3,
+    // A directory on disk, optionally serving templates, scripts and compose files straight from a checkout of the repo
+    Local {
+        root: String,
+        #[serde(default)]
+        deployment_dir: Option<String>,
+    },
+}
+
+// What to do with whatever a failed `terraform apply` managed to create
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RollbackPolicy {
+    // Destroy the partial infrastructure and remove the rendered templates
+    #[default]
+    Destroy,
+    // Leave everything in place so it can be inspected, then undeployed by hand
+    Keep,
+}
+
+impl RollbackPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RollbackPolicy::Destroy => "destroy",
+            RollbackPolicy::Keep => "keep",
+        }
+    }
+}
+
+fn default_deploy_workers() -> usize {
+    2
 }
 
 
@@ -34,5 +108,14 @@ pub fn load_config(path: &str) -> Result<AppConfig, Box<dyn std::error::Error>>
             .expect("S3_BUCKET environment variable must be set when s3_bucket is 'env'");
     }
 
+    if config.operators.is_empty() {
+        config.operators = env::var("OPERATOR_EMAILS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|email| email.trim().to_string())
+            .filter(|email| !email.is_empty())
+            .collect();
+    }
+
     Ok(config)
 }
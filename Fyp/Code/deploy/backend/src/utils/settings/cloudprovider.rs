@@ -1,8 +1,13 @@
 use serde::{Deserialize, Serialize};
-use actix_web::{web, Responder};
+use actix_web::{web, HttpResponse, Responder};
+use serde_json::json;
 
 use crate::{
-    db::update_provider_handler, 
+    deploy::ApiResponse,
+    utils::deployment::upgrade::error_response,
+    utils::providers::cloud_provider::find_provider,
+    utils::settings::credentials::{find_user_id, store_default_credential},
+    utils::settings::keyring::Keyring,
     utils::user::signup_func::init_mongo_client,
 };
 
@@ -10,16 +15,43 @@ use crate::{
 pub struct ProviderRequest {
     pub user_email: String,
     pub provider_key: String,
+    // Older clients only ever sent Hetzner tokens
+    #[serde(default = "default_provider")]
+    pub provider: String,
 }
 
-pub async fn update_provider(request: web::Json<ProviderRequest>) -> impl Responder {
-    
+fn default_provider() -> String {
+    "hetzner".into()
+}
+
+// Kept for older clients: sets the user's default credential for one provider
+pub async fn update_provider(request: web::Json<ProviderRequest>, keyring: web::Data<Keyring>) -> impl Responder {
+
     let mongo_client=init_mongo_client().await;
     let request = request.into_inner();
 
-    match update_provider_handler(mongo_client.clone(), &request).await {
-        Ok(success_resp) => success_resp,
-        Err(error_resp) => error_resp,
+    let Some(provider) = find_provider(&request.provider) else {
+        return error_response(HttpResponse::BadRequest(), format!("Unsupported cloud provider '{}'", request.provider));
+    };
+    let fields = match provider.parse_credential(&request.provider_key) {
+        Ok(fields) => fields,
+        Err(e) => return error_response(HttpResponse::BadRequest(), format!("Invalid {} credential: {}", provider.name(), e)),
+    };
+
+    let user_id = match find_user_id(mongo_client.clone(), &request.user_email).await {
+        Ok(user_id) => user_id,
+        Err(resp) => return resp,
+    };
+
+    match store_default_credential(mongo_client, &keyring, &user_id, provider, &fields).await {
+        Ok(credential_id) => HttpResponse::Ok().json(ApiResponse {
+            status: "success".into(),
+            message: "Cloud provider token updated successfully".into(),
+            returneddata: Some(json!({ "credential_id": credential_id })),
+        }),
+        Err(e) => {
+            eprintln!("❌ Failed to store cloud provider key: {}", e);
+            error_response(HttpResponse::InternalServerError(), "Failed to update cloud provider key")
+        }
     }
 }
-
@@ -0,0 +1,495 @@
+use std::collections::HashMap;
+use actix_web::{web, HttpResponse, Responder};
+use chrono::Utc;
+use mongodb::{
+    bson::{oid::ObjectId, Document},
+    Client,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::{
+    deploy::ApiResponse,
+    utils::database::db::{
+        count_deployments_using_credential, delete_cloud_credential, find_cloud_credential, find_cloud_credential_by_name, find_credentials_to_reseal,
+        find_legacy_cloud_provider_tokens, find_user_by_email, insert_cloud_credential, is_duplicate_key, list_cloud_credentials,
+        remove_legacy_cloud_provider_token, store_resealed_credential, update_cloud_credential,
+    },
+    utils::deployment::upgrade::error_response,
+    utils::providers::cloud_provider::{deployment_provider, find_provider, CloudProvider, Credential},
+    utils::settings::keyring::{Keyring, SealedSecret},
+    utils::terraform::variables::TerraformSecrets,
+    utils::user::signup_func::init_mongo_client,
+};
+
+// Used when a deployment does not name a credential, and written by the legacy /settings endpoint
+pub const DEFAULT_CREDENTIAL_NAME: &str = "default";
+
+const MAX_NAME_LENGTH: usize = 64;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CloudCredential {
+    pub credential_id: String,
+    pub user_id: ObjectId,
+    pub name: String,
+    // Id of the CloudProvider the fields are for
+    pub provider: String,
+    // The fields as JSON, sealed with the keyring's active key at the time of writing
+    pub sealed: SealedSecret,
+    // Masked field values, so listing credentials never needs the key
+    pub hints: HashMap<String, String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Deserialize)]
+pub struct CredentialQuery {
+    pub user_email: String,
+}
+
+#[derive(Deserialize)]
+pub struct CreateCredentialRequest {
+    pub user_email: String,
+    pub name: String,
+    pub provider: String,
+    pub fields: Credential,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateCredentialRequest {
+    pub user_email: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    // Replaces all of the credential's fields
+    #[serde(default)]
+    pub fields: Option<Credential>,
+}
+
+fn validate_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Credential name is required".into());
+    }
+    if name.chars().count() > MAX_NAME_LENGTH || name.chars().any(char::is_control) {
+        return Err(format!("Credential name must be at most {} printable characters", MAX_NAME_LENGTH));
+    }
+    Ok(name.to_string())
+}
+
+// Enough of the value to tell two credentials apart, never enough to use it
+fn mask(value: &str) -> String {
+    let chars: Vec<char> = value.chars().collect();
+    if chars.len() <= 8 {
+        return "****".into();
+    }
+    format!("****{}", chars[chars.len() - 4..].iter().collect::<String>())
+}
+
+fn masked(credential: &CloudCredential) -> Value {
+    json!({
+        "credential_id": credential.credential_id,
+        "name": credential.name,
+        "provider": credential.provider,
+        "fields": credential.hints,
+        "created_at": credential.created_at,
+        "updated_at": credential.updated_at,
+    })
+}
+
+// Ties a sealed value to its record, so it cannot be copied onto another user's or provider's credential
+fn seal_context(user_id: &ObjectId, credential_id: &str, provider: &str) -> String {
+    format!("{}:{}:{}", user_id.to_hex(), credential_id, provider)
+}
+
+fn seal_fields(keyring: &Keyring, user_id: &ObjectId, credential_id: &str, provider: &str, fields: &Credential) -> Result<(SealedSecret, HashMap<String, String>), String> {
+    let plaintext = serde_json::to_vec(fields).map_err(|e| e.to_string())?;
+    let sealed = keyring.seal(&plaintext, seal_context(user_id, credential_id, provider).as_bytes())?;
+    let hints = fields.iter().map(|(field, value)| (field.clone(), mask(value))).collect();
+    Ok((sealed, hints))
+}
+
+fn open_fields(keyring: &Keyring, credential: &CloudCredential) -> Result<Credential, String> {
+    let context = seal_context(&credential.user_id, &credential.credential_id, &credential.provider);
+    let plaintext = keyring.open(&credential.sealed, context.as_bytes())?;
+    serde_json::from_slice(&plaintext).map_err(|_| "Sealed credential is not a set of fields".to_string())
+}
+
+pub async fn find_user_id(mongo_client: Client, user_email: &str) -> Result<ObjectId, HttpResponse> {
+    match find_user_by_email(mongo_client, user_email).await {
+        Ok(Some(user_doc)) => user_doc
+            .get_object_id("_id")
+            .map_err(|_| error_response(HttpResponse::InternalServerError(), "Failed to extract user ID from user document")),
+        Ok(None) => Err(error_response(HttpResponse::BadRequest(), format!("User with email '{}' not found", user_email))),
+        Err(e) => {
+            eprintln!("❌ Error finding user by email: {}", e);
+            Err(error_response(HttpResponse::InternalServerError(), "Internal server error while looking up user"))
+        }
+    }
+}
+
+// The named credential, or the user's default one for the provider (or their only one) when none is named
+pub async fn resolve_credential(mongo_client: Client, user_id: &ObjectId, provider: &dyn CloudProvider, credential_id: Option<&str>) -> Result<CloudCredential, String> {
+    let credential = match credential_id {
+        Some(credential_id) => match find_cloud_credential(mongo_client, user_id, credential_id).await {
+            Ok(Some(credential)) => credential,
+            Ok(None) => return Err(format!("Credential '{}' not found", credential_id)),
+            Err(e) => {
+                eprintln!("❌ Error loading credential {}: {}", credential_id, e);
+                return Err("Failed to load cloud credentials".into());
+            }
+        },
+        None => {
+            let credentials = match list_cloud_credentials(mongo_client, user_id).await {
+                Ok(credentials) => credentials,
+                Err(e) => {
+                    eprintln!("❌ Error listing credentials: {}", e);
+                    return Err("Failed to load cloud credentials".into());
+                }
+            };
+            let mut candidates: Vec<CloudCredential> = credentials.into_iter().filter(|credential| credential.provider == provider.id()).collect();
+
+            match candidates.iter().position(|credential| credential.name == DEFAULT_CREDENTIAL_NAME) {
+                Some(index) => candidates.swap_remove(index),
+                None if candidates.len() == 1 => candidates.remove(0),
+                None if candidates.is_empty() => return Err(format!("No {} credential is set for this user", provider.name())),
+                None => return Err(format!("Several {} credentials exist, choose one with credential_id", provider.name())),
+            }
+        }
+    };
+
+    if credential.provider != provider.id() {
+        return Err(format!("Credential '{}' is for {}, not {}", credential.name, credential.provider, provider.name()));
+    }
+
+    Ok(credential)
+}
+
+// Which credential a Terraform run uses. Jobs and handlers carry this around and only open it right before Terraform starts
+#[derive(Clone)]
+pub struct CredentialRef {
+    pub user_id: ObjectId,
+    pub provider: &'static dyn CloudProvider,
+    pub credential_id: String,
+}
+
+impl CredentialRef {
+    pub async fn resolve(mongo_client: Client, user_id: &ObjectId, provider: &'static dyn CloudProvider, credential_id: Option<&str>) -> Result<CredentialRef, String> {
+        let credential = resolve_credential(mongo_client, user_id, provider, credential_id).await?;
+        Ok(CredentialRef {
+            user_id: *user_id,
+            provider,
+            credential_id: credential.credential_id,
+        })
+    }
+
+    // The credential an existing deployment was created with
+    pub async fn for_deployment(mongo_client: Client, deployment: &Document) -> Result<CredentialRef, String> {
+        let provider = deployment_provider(deployment)?;
+        let user_id = deployment.get_object_id("user_id").map_err(|_| "Deployment has no owner".to_string())?;
+        // Deployments made before named credentials use the owner's default one
+        let credential_id = deployment.get_str("credential_id").ok();
+
+        CredentialRef::resolve(mongo_client, &user_id, provider, credential_id).await
+    }
+
+    // Decrypts the credential and maps it onto the provider's template variables
+    pub async fn secrets(&self, mongo_client: Client, keyring: &Keyring) -> Result<TerraformSecrets, String> {
+        let credential = match find_cloud_credential(mongo_client, &self.user_id, &self.credential_id).await {
+            Ok(Some(credential)) => credential,
+            Ok(None) => return Err(format!("Credential '{}' no longer exists", self.credential_id)),
+            Err(e) => {
+                eprintln!("❌ Error loading credential {}: {}", self.credential_id, e);
+                return Err("Failed to load cloud credentials".into());
+            }
+        };
+
+        let fields = open_fields(keyring, &credential).map_err(|e| {
+            eprintln!("❌ Failed to open credential {}: {}", self.credential_id, e);
+            format!("Credential '{}' could not be decrypted", credential.name)
+        })?;
+        self.provider
+            .validate_credential(&fields)
+            .map_err(|e| format!("Credential '{}' is invalid: {}", credential.name, e))?;
+
+        Ok(self.provider.secrets(&fields))
+    }
+}
+
+// Creates or replaces the user's credential named DEFAULT_CREDENTIAL_NAME for the provider and returns its id
+pub async fn store_default_credential(mongo_client: Client, keyring: &Keyring, user_id: &ObjectId, provider: &dyn CloudProvider, fields: &Credential) -> Result<String, String> {
+    let existing = find_cloud_credential_by_name(mongo_client.clone(), user_id, provider.id(), DEFAULT_CREDENTIAL_NAME)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if let Some(credential) = existing {
+        let (sealed, hints) = seal_fields(keyring, user_id, &credential.credential_id, provider.id(), fields)?;
+        update_cloud_credential(mongo_client, user_id, &credential.credential_id, None, Some((&sealed, &hints)))
+            .await
+            .map_err(|e| e.to_string())?;
+        return Ok(credential.credential_id);
+    }
+
+    let now = Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    let credential_id = Uuid::new_v4().to_string();
+    let (sealed, hints) = seal_fields(keyring, user_id, &credential_id, provider.id(), fields)?;
+    let credential = CloudCredential {
+        credential_id,
+        user_id: *user_id,
+        name: DEFAULT_CREDENTIAL_NAME.into(),
+        provider: provider.id().to_string(),
+        sealed,
+        hints,
+        created_at: now.clone(),
+        updated_at: now,
+    };
+
+    insert_cloud_credential(mongo_client, &credential).await.map_err(|e| e.to_string())?;
+    Ok(credential.credential_id)
+}
+
+// Fields of a stored credential that still needs sealing under the active key: plaintext from before encryption, or sealed with an older key
+fn stored_fields(keyring: &Keyring, record: &Document) -> Result<Credential, String> {
+    if let Ok(fields) = record.get_document("fields") {
+        return mongodb::bson::from_document(fields.clone()).map_err(|e| e.to_string());
+    }
+
+    let credential: CloudCredential = mongodb::bson::from_document(record.clone()).map_err(|e| e.to_string())?;
+    open_fields(keyring, &credential)
+}
+
+// Seals anything stored in plaintext and re-seals values under retired keys, so an old key can be dropped once this has run
+pub async fn migrate_cloud_credentials(mongo_client: &Client, keyring: &Keyring) {
+    // Before named credentials, each user had one Hetzner token in users.CloudProvider; it becomes their default Hetzner credential
+    match find_legacy_cloud_provider_tokens(mongo_client).await {
+        Ok(tokens) => {
+            let hetzner = find_provider("hetzner").expect("Hetzner provider is registered");
+            for (user_id, token) in tokens {
+                let fields = Credential::from([("token".to_string(), token)]);
+                if let Err(e) = store_default_credential(mongo_client.clone(), keyring, &user_id, hetzner, &fields).await {
+                    eprintln!("❌ Failed to migrate cloud provider token for user {}: {}", user_id, e);
+                    continue;
+                }
+                match remove_legacy_cloud_provider_token(mongo_client, &user_id).await {
+                    Ok(()) => println!("🔑 Moved user {}'s cloud provider token to a default Hetzner credential", user_id),
+                    Err(e) => eprintln!("❌ Failed to remove legacy cloud provider token for user {}: {}", user_id, e),
+                }
+            }
+        }
+        Err(e) => eprintln!("❌ Failed to look up legacy cloud provider tokens: {}", e),
+    }
+
+    let records = match find_credentials_to_reseal(mongo_client, keyring.active_key_id()).await {
+        Ok(records) => records,
+        Err(e) => {
+            eprintln!("❌ Failed to look up credentials to seal: {}", e);
+            return;
+        }
+    };
+
+    for record in records {
+        let (Ok(user_id), Ok(credential_id), Ok(provider)) = (record.get_object_id("user_id"), record.get_str("credential_id"), record.get_str("provider")) else {
+            continue;
+        };
+        let resealed = stored_fields(keyring, &record).and_then(|fields| seal_fields(keyring, &user_id, credential_id, provider, &fields));
+
+        let (sealed, hints) = match resealed {
+            Ok(resealed) => resealed,
+            Err(e) => {
+                eprintln!("❌ Failed to seal credential {}: {}", credential_id, e);
+                continue;
+            }
+        };
+        match store_resealed_credential(mongo_client, credential_id, &sealed, &hints).await {
+            Ok(()) => println!("🔑 Sealed credential {} with key '{}'", credential_id, sealed.key_id),
+            Err(e) => eprintln!("❌ Failed to store sealed credential {}: {}", credential_id, e),
+        }
+    }
+}
+
+pub async fn create_credential(request: web::Json<CreateCredentialRequest>, keyring: web::Data<Keyring>) -> impl Responder {
+    let Some(provider) = find_provider(&request.provider) else {
+        return error_response(HttpResponse::BadRequest(), format!("Unsupported cloud provider '{}'", request.provider));
+    };
+    let name = match validate_name(&request.name) {
+        Ok(name) => name,
+        Err(message) => return error_response(HttpResponse::BadRequest(), message),
+    };
+    if let Err(e) = provider.validate_credential(&request.fields) {
+        return error_response(HttpResponse::BadRequest(), format!("Invalid {} credential: {}", provider.name(), e));
+    }
+
+    let mongo_client = init_mongo_client().await;
+    let user_id = match find_user_id(mongo_client.clone(), &request.user_email).await {
+        Ok(user_id) => user_id,
+        Err(resp) => return resp,
+    };
+
+    let now = Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    let credential_id = Uuid::new_v4().to_string();
+    let (sealed, hints) = match seal_fields(&keyring, &user_id, &credential_id, provider.id(), &request.fields) {
+        Ok(sealed) => sealed,
+        Err(e) => {
+            eprintln!("❌ Failed to seal credential: {}", e);
+            return error_response(HttpResponse::InternalServerError(), "Failed to store credential");
+        }
+    };
+    let credential = CloudCredential {
+        credential_id,
+        user_id,
+        name,
+        provider: provider.id().to_string(),
+        sealed,
+        hints,
+        created_at: now.clone(),
+        updated_at: now,
+    };
+
+    match insert_cloud_credential(mongo_client, &credential).await {
+        Ok(()) => {
+            println!("🔑 Stored {} credential '{}' for {}", provider.name(), credential.name, request.user_email);
+            HttpResponse::Created().json(ApiResponse {
+                status: "success".into(),
+                message: "Credential created successfully".into(),
+                returneddata: Some(masked(&credential)),
+            })
+        }
+        Err(e) if is_duplicate_key(&e) => error_response(HttpResponse::Conflict(), format!("A {} credential named '{}' already exists", provider.name(), credential.name)),
+        Err(e) => {
+            eprintln!("❌ Failed to store credential: {}", e);
+            error_response(HttpResponse::InternalServerError(), "Failed to store credential")
+        }
+    }
+}
+
+pub async fn list_credentials(query: web::Query<CredentialQuery>) -> impl Responder {
+    let mongo_client = init_mongo_client().await;
+    let user_id = match find_user_id(mongo_client.clone(), &query.user_email).await {
+        Ok(user_id) => user_id,
+        Err(resp) => return resp,
+    };
+
+    match list_cloud_credentials(mongo_client, &user_id).await {
+        Ok(credentials) => HttpResponse::Ok().json(ApiResponse {
+            status: "success".into(),
+            message: if credentials.is_empty() {
+                "No credentials found".into()
+            } else {
+                "Credentials fetched successfully".into()
+            },
+            returneddata: Some(json!({ "credentials": credentials.iter().map(masked).collect::<Vec<_>>() })),
+        }),
+        Err(e) => {
+            eprintln!("❌ Error listing credentials: {}", e);
+            error_response(HttpResponse::InternalServerError(), "Failed to fetch credentials")
+        }
+    }
+}
+
+pub async fn update_credential(path: web::Path<String>, request: web::Json<UpdateCredentialRequest>, keyring: web::Data<Keyring>) -> impl Responder {
+    let credential_id = path.into_inner();
+    if request.name.is_none() && request.fields.is_none() {
+        return error_response(HttpResponse::BadRequest(), "Nothing to change, give a name or fields");
+    }
+
+    let mongo_client = init_mongo_client().await;
+    let user_id = match find_user_id(mongo_client.clone(), &request.user_email).await {
+        Ok(user_id) => user_id,
+        Err(resp) => return resp,
+    };
+
+    let credential = match find_cloud_credential(mongo_client.clone(), &user_id, &credential_id).await {
+        Ok(Some(credential)) => credential,
+        Ok(None) => return error_response(HttpResponse::NotFound(), "Credential not found"),
+        Err(e) => {
+            eprintln!("❌ Error loading credential {}: {}", credential_id, e);
+            return error_response(HttpResponse::InternalServerError(), "Failed to load credential");
+        }
+    };
+
+    let name = match request.name.as_deref().map(validate_name).transpose() {
+        Ok(name) => name,
+        Err(message) => return error_response(HttpResponse::BadRequest(), message),
+    };
+    let sealed = match &request.fields {
+        Some(fields) => {
+            // The provider is fixed, so the new fields have to fit it
+            let Some(provider) = find_provider(&credential.provider) else {
+                return error_response(HttpResponse::Conflict(), format!("Credential is for unknown provider '{}'", credential.provider));
+            };
+            if let Err(e) = provider.validate_credential(fields) {
+                return error_response(HttpResponse::BadRequest(), format!("Invalid {} credential: {}", provider.name(), e));
+            }
+            match seal_fields(&keyring, &user_id, &credential_id, provider.id(), fields) {
+                Ok(sealed) => Some(sealed),
+                Err(e) => {
+                    eprintln!("❌ Failed to seal credential {}: {}", credential_id, e);
+                    return error_response(HttpResponse::InternalServerError(), "Failed to update credential");
+                }
+            }
+        }
+        None => None,
+    };
+
+    match update_cloud_credential(mongo_client, &user_id, &credential_id, name.as_deref(), sealed.as_ref().map(|(sealed, hints)| (sealed, hints))).await {
+        Ok(true) => HttpResponse::Ok().json(ApiResponse {
+            status: "success".into(),
+            message: "Credential updated successfully".into(),
+            returneddata: None,
+        }),
+        Ok(false) => error_response(HttpResponse::NotFound(), "Credential not found"),
+        Err(e) if is_duplicate_key(&e) => error_response(HttpResponse::Conflict(), format!("A credential named '{}' already exists for this provider", name.unwrap_or_default())),
+        Err(e) => {
+            eprintln!("❌ Failed to update credential {}: {}", credential_id, e);
+            error_response(HttpResponse::InternalServerError(), "Failed to update credential")
+        }
+    }
+}
+
+pub async fn delete_credential(path: web::Path<String>, query: web::Query<CredentialQuery>) -> impl Responder {
+    let credential_id = path.into_inner();
+    let mongo_client = init_mongo_client().await;
+    let user_id = match find_user_id(mongo_client.clone(), &query.user_email).await {
+        Ok(user_id) => user_id,
+        Err(resp) => return resp,
+    };
+
+    // Someone else's credential is reported as missing, without revealing whether it is in use
+    let credential = match find_cloud_credential(mongo_client.clone(), &user_id, &credential_id).await {
+        Ok(Some(credential)) => credential,
+        Ok(None) => return error_response(HttpResponse::NotFound(), "Credential not found"),
+        Err(e) => {
+            eprintln!("❌ Error loading credential {}: {}", credential_id, e);
+            return error_response(HttpResponse::InternalServerError(), "Failed to delete credential");
+        }
+    };
+
+    // Deployments without a credential_id use whichever credential resolves as the default, so they count against that one
+    let is_default = match find_provider(&credential.provider) {
+        Some(provider) => matches!(resolve_credential(mongo_client.clone(), &user_id, provider, None).await, Ok(default) if default.credential_id == credential_id),
+        None => false,
+    };
+
+    // Undeploy needs the same credential, so it stays until the deployments using it are destroyed
+    match count_deployments_using_credential(mongo_client.clone(), &credential_id, is_default.then_some((&user_id, credential.provider.as_str()))).await {
+        Ok(0) => {}
+        Ok(count) => return error_response(HttpResponse::Conflict(), format!("Credential is still used by {} deployment(s); undeploy them first", count)),
+        Err(e) => {
+            eprintln!("❌ Error checking credential {} usage: {}", credential_id, e);
+            return error_response(HttpResponse::InternalServerError(), "Failed to delete credential");
+        }
+    }
+
+    match delete_cloud_credential(mongo_client, &user_id, &credential_id).await {
+        Ok(true) => HttpResponse::Ok().json(ApiResponse {
+            status: "success".into(),
+            message: "Credential deleted successfully".into(),
+            returneddata: None,
+        }),
+        Ok(false) => error_response(HttpResponse::NotFound(), "Credential not found"),
+        Err(e) => {
+            eprintln!("❌ Failed to delete credential {}: {}", credential_id, e);
+            error_response(HttpResponse::InternalServerError(), "Failed to delete credential")
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+use std::env;
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    Aes256Gcm, Key, Nonce,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+
+use crate::app_config::{AppConfig, CredentialKeysConfig};
+
+const NONCE_LENGTH: usize = 12;
+
+// Ciphertext plus what is needed to open it again; the key itself never leaves the keyring
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SealedSecret {
+    pub key_id: String,
+    // Base64, fresh for every seal
+    pub nonce: String,
+    // Base64 AES-256-GCM output, authentication tag included
+    pub ciphertext: String,
+}
+
+// Master keys by id. New secrets are sealed with the active key; older keys stay only to open what they sealed
+pub struct Keyring {
+    active: String,
+    keys: HashMap<String, Aes256Gcm>,
+}
+
+impl Keyring {
+    // From `credential_keys` in the config, or CREDENTIAL_KEYS ("id:base64,id:base64") and CREDENTIAL_ACTIVE_KEY when it is left out
+    pub fn from_config(config: &AppConfig) -> Result<Keyring, String> {
+        let keys_config = match &config.credential_keys {
+            Some(keys_config) => keys_config.clone(),
+            None => CredentialKeysConfig {
+                active: env::var("CREDENTIAL_ACTIVE_KEY").map_err(|_| "CREDENTIAL_ACTIVE_KEY environment variable must be set when credential_keys is not configured")?,
+                keys: env::var("CREDENTIAL_KEYS")
+                    .map_err(|_| "CREDENTIAL_KEYS environment variable must be set when credential_keys is not configured")?
+                    .split(',')
+                    .filter(|entry| !entry.trim().is_empty())
+                    .map(|entry| {
+                        entry
+                            .split_once(':')
+                            .map(|(id, key)| (id.trim().to_string(), key.trim().to_string()))
+                            .ok_or_else(|| "CREDENTIAL_KEYS entries must look like '<id>:<base64 key>'".to_string())
+                    })
+                    .collect::<Result<_, _>>()?,
+            },
+        };
+
+        let mut keys = HashMap::new();
+        for (id, key) in &keys_config.keys {
+            let key = STANDARD.decode(key).map_err(|_| format!("Credential key '{}' is not valid base64", id))?;
+            if key.len() != 32 {
+                return Err(format!("Credential key '{}' must be 32 bytes, got {}", id, key.len()));
+            }
+            keys.insert(id.clone(), Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)));
+        }
+
+        if !keys.contains_key(&keys_config.active) {
+            return Err(format!("Active credential key '{}' is not one of the configured keys", keys_config.active));
+        }
+
+        Ok(Keyring {
+            active: keys_config.active,
+            keys,
+        })
+    }
+
+    pub fn active_key_id(&self) -> &str {
+        &self.active
+    }
+
+    // `context` is bound into the tag, so a sealed value only opens for the record it was sealed for
+    pub fn seal(&self, plaintext: &[u8], context: &[u8]) -> Result<SealedSecret, String> {
+        let cipher = &self.keys[&self.active];
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(&nonce, Payload { msg: plaintext, aad: context })
+            .map_err(|_| "Failed to encrypt secret".to_string())?;
+
+        Ok(SealedSecret {
+            key_id: self.active.clone(),
+            nonce: STANDARD.encode(nonce),
+            ciphertext: STANDARD.encode(ciphertext),
+        })
+    }
+
+    pub fn open(&self, sealed: &SealedSecret, context: &[u8]) -> Result<Vec<u8>, String> {
+        let cipher = self
+            .keys
+            .get(&sealed.key_id)
+            .ok_or_else(|| format!("Secret was sealed with unknown key '{}'", sealed.key_id))?;
+
+        let nonce = STANDARD.decode(&sealed.nonce).ok().filter(|nonce| nonce.len() == NONCE_LENGTH).ok_or("Sealed secret has an invalid nonce")?;
+        let ciphertext = STANDARD.decode(&sealed.ciphertext).map_err(|_| "Sealed secret is not valid base64")?;
+
+        cipher
+            .decrypt(Nonce::from_slice(&nonce), Payload { msg: &ciphertext, aad: context })
+            .map_err(|_| "Sealed secret failed authentication".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::Keyring;
+    use crate::app_config::AppConfig;
+
+    const OLD_KEY: &str = "MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY=";
+    const NEW_KEY: &str = "ZmVkY2JhOTg3NjU0MzIxMGZlZGNiYTk4NzY1NDMyMTA=";
+
+    fn keyring(active: &str, keys: &[(&str, &str)]) -> Keyring {
+        let config: AppConfig = serde_json::from_value(json!({
+            "mongo_uri": "mongodb://localhost",
+            "credential_keys": { "active": active, "keys": keys.iter().copied().collect::<std::collections::HashMap<_, _>>() },
+        }))
+        .unwrap();
+        Keyring::from_config(&config).unwrap()
+    }
+
+    #[test]
+    fn sealed_secret_opens_with_the_same_context() {
+        let keyring = keyring("2024-06", &[("2024-06", OLD_KEY)]);
+        let sealed = keyring.seal(b"hcloud-token", b"credential:abc").unwrap();
+
+        assert_eq!(sealed.key_id, "2024-06");
+        assert_ne!(sealed.ciphertext, "hcloud-token");
+        assert_eq!(keyring.open(&sealed, b"credential:abc").unwrap(), b"hcloud-token");
+    }
+
+    #[test]
+    fn sealed_secret_does_not_open_for_another_record() {
+        let keyring = keyring("2024-06", &[("2024-06", OLD_KEY)]);
+        let sealed = keyring.seal(b"hcloud-token", b"credential:abc").unwrap();
+
+        assert_eq!(keyring.open(&sealed, b"credential:xyz").unwrap_err(), "Sealed secret failed authentication");
+    }
+
+    #[test]
+    fn secret_sealed_with_an_unknown_key_does_not_open() {
+        let sealed = keyring("2024-06", &[("2024-06", OLD_KEY)]).seal(b"hcloud-token", b"credential:abc").unwrap();
+        let rotated = keyring("2025-01", &[("2025-01", NEW_KEY)]);
+
+        assert_eq!(rotated.open(&sealed, b"credential:abc").unwrap_err(), "Secret was sealed with unknown key '2024-06'");
+    }
+
+    #[test]
+    fn rotation_reseals_under_the_new_active_key() {
+        let sealed = keyring("2024-06", &[("2024-06", OLD_KEY)]).seal(b"hcloud-token", b"credential:abc").unwrap();
+
+        // Both keys configured with the new one active, as during the restart that re-seals everything
+        let rotating = keyring("2025-01", &[("2024-06", OLD_KEY), ("2025-01", NEW_KEY)]);
+        let plaintext = rotating.open(&sealed, b"credential:abc").unwrap();
+        let resealed = rotating.seal(&plaintext, b"credential:abc").unwrap();
+        assert_eq!(resealed.key_id, "2025-01");
+
+        // Once the old key is removed, only the re-sealed copy still opens
+        let rotated = keyring("2025-01", &[("2025-01", NEW_KEY)]);
+        assert_eq!(rotated.open(&resealed, b"credential:abc").unwrap(), b"hcloud-token");
+        assert!(rotated.open(&sealed, b"credential:abc").is_err());
+    }
+}
@@ -1,2 +1,4 @@
 pub mod app_config;
-pub mod cloudprovider;
\ No newline at end of file
+pub mod cloudprovider;
+pub mod credentials;
+pub mod keyring;
\ No newline at end of file
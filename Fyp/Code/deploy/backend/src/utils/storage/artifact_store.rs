@@ -0,0 +1,53 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use async_trait::async_trait;
+
+use crate::app_config::{AppConfig, StorageConfig};
+use crate::utils::storage::local_store::LocalStore;
+use crate::utils::storage::s3_store::S3Store;
+
+pub type StoreResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+// Where templates are read from and deployment artifacts (rendered folders, run logs, state) are written to.
+// Keys are `/`-separated paths such as `terraform/hetzner/main.tf`; a prefix is a key ending in `/`.
+#[async_trait]
+pub trait ArtifactStore: Send + Sync {
+    // Every key under `prefix`, sorted
+    async fn list(&self, prefix: &str) -> StoreResult<Vec<String>>;
+
+    // None when the key does not exist
+    async fn get(&self, key: &str) -> StoreResult<Option<Vec<u8>>>;
+
+    async fn put(&self, key: &str, body: Vec<u8>) -> StoreResult<()>;
+
+    async fn copy(&self, source_key: &str, destination_key: &str) -> StoreResult<()>;
+
+    // Deleting a key that does not exist is not an error
+    async fn delete(&self, key: &str) -> StoreResult<()>;
+
+    // Returns how many keys were deleted
+    async fn delete_prefix(&self, prefix: &str) -> StoreResult<usize>;
+
+    // The `terraform { backend ... }` block that keeps a project's state next to its other artifacts
+    fn terraform_backend(&self, project_id: &str) -> String;
+
+    // Called before Terraform reads or writes a project's state, for backends that need somewhere to put it
+    async fn prepare_terraform_state(&self, _project_id: &str) -> StoreResult<()> {
+        Ok(())
+    }
+
+    // Short human-readable description for startup logs
+    fn describe(&self) -> String;
+}
+
+pub async fn build_store(app_config: &AppConfig) -> Arc<dyn ArtifactStore> {
+    match &app_config.storage {
+        StorageConfig::S3 => {
+            let aws_config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
+            Arc::new(S3Store::new(aws_sdk_s3::Client::new(&aws_config), &app_config.s3_bucket, &app_config.aws_region))
+        }
+        StorageConfig::Local { root, deployment_dir } => {
+            Arc::new(LocalStore::new(PathBuf::from(root), deployment_dir.as_ref().map(PathBuf::from)))
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use std::io::ErrorKind;
+use std::path::{Component, Path, PathBuf};
+use async_trait::async_trait;
+use tokio::fs as async_fs;
+
+use crate::terraform_handler::{state_key, state_prefix};
+use crate::utils::storage::artifact_store::{ArtifactStore, StoreResult};
+
+// Keys under these prefixes are served from `deployment_dir` when one is configured
+const DEPLOYMENT_PREFIXES: [&str; 3] = ["terraform/", "scripts/", "compose/"];
+
+// Keeps everything in a directory on disk so the backend can run without AWS
+pub struct LocalStore {
+    root: PathBuf,
+    // Lets the repo's `deployment` folder stand in for the templates, scripts and compose files without copying them
+    deployment_dir: Option<PathBuf>,
+}
+
+impl LocalStore {
+    pub fn new(root: PathBuf, deployment_dir: Option<PathBuf>) -> LocalStore {
+        LocalStore { root, deployment_dir }
+    }
+
+    // The directory a key lives under
+    fn base_for(&self, key: &str) -> &Path {
+        match &self.deployment_dir {
+            Some(deployment_dir) if DEPLOYMENT_PREFIXES.iter().any(|prefix| key.starts_with(prefix)) => deployment_dir,
+            _ => &self.root,
+        }
+    }
+
+    fn path_for(&self, key: &str) -> StoreResult<PathBuf> {
+        let base = self.base_for(key);
+        let relative = Path::new(key);
+
+        // Keys come from template and project names, so never let one climb out of the store
+        if relative.components().any(|component| !matches!(component, Component::Normal(_))) {
+            return Err(format!("Invalid storage key '{}'", key).into());
+        }
+
+        Ok(base.join(relative))
+    }
+}
+
+// Every file below `dir`, as `/`-separated paths relative to it
+async fn walk_files(dir: &Path) -> StoreResult<Vec<String>> {
+    let mut files = Vec::new();
+    let mut pending = vec![(dir.to_path_buf(), String::new())];
+
+    while let Some((current, relative)) = pending.pop() {
+        let mut entries = match async_fs::read_dir(&current).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let child = format!("{}{}", relative, name);
+
+            if entry.file_type().await?.is_dir() {
+                pending.push((entry.path(), format!("{}/", child)));
+            } else {
+                files.push(child);
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+#[async_trait]
+impl ArtifactStore for LocalStore {
+    async fn list(&self, prefix: &str) -> StoreResult<Vec<String>> {
+        // Walk the deepest directory the prefix names, then filter on the rest of it
+        let directory = &prefix[..prefix.rfind('/').map(|i| i + 1).unwrap_or(0)];
+        let directory_path = self.path_for(directory)?;
+
+        let mut keys: Vec<String> = walk_files(&directory_path)
+            .await?
+            .into_iter()
+            .map(|relative| format!("{}{}", directory, relative))
+            .filter(|key| key.starts_with(prefix))
+            .collect();
+
+        keys.sort();
+        Ok(keys)
+    }
+
+    async fn get(&self, key: &str) -> StoreResult<Option<Vec<u8>>> {
+        match async_fs::read(self.path_for(key)?).await {
+            Ok(contents) => Ok(Some(contents)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn put(&self, key: &str, body: Vec<u8>) -> StoreResult<()> {
+        let path = self.path_for(key)?;
+        if let Some(parent) = path.parent() {
+            async_fs::create_dir_all(parent).await?;
+        }
+        async_fs::write(path, body).await?;
+        Ok(())
+    }
+
+    async fn copy(&self, source_key: &str, destination_key: &str) -> StoreResult<()> {
+        let contents = self
+            .get(source_key)
+            .await?
+            .ok_or_else(|| format!("Storage key '{}' not found", source_key))?;
+        self.put(destination_key, contents).await
+    }
+
+    async fn delete(&self, key: &str) -> StoreResult<()> {
+        match async_fs::remove_file(self.path_for(key)?).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    async fn delete_prefix(&self, prefix: &str) -> StoreResult<usize> {
+        let keys = self.list(prefix).await?;
+
+        for key in &keys {
+            async_fs::remove_file(self.path_for(key)?).await?;
+        }
+
+        // Drop the emptied folder too, as S3 would show nothing left under the prefix
+        if prefix.ends_with('/') {
+            let directory = self.path_for(prefix)?;
+            if let Err(e) = async_fs::remove_dir_all(&directory).await {
+                if e.kind() != ErrorKind::NotFound {
+                    return Err(e.into());
+                }
+            }
+        }
+
+        Ok(keys.len())
+    }
+
+    // Terraform's own local backend, writing state into the store next to everything else
+    fn terraform_backend(&self, project_id: &str) -> String {
+        let state_path = std::path::absolute(self.root.join(state_key(project_id))).unwrap_or_else(|_| self.root.join(state_key(project_id)));
+
+        // JSON string quoting is valid HCL, so odd characters in the path cannot break the block
+        format!(
+            "terraform {{\n  backend \"local\" {{\n    path = {}\n  }}\n}}\n",
+            serde_json::to_string(&state_path.to_string_lossy()).unwrap_or_default()
+        )
+    }
+
+    // The local backend writes the state file but not the folders above it
+    async fn prepare_terraform_state(&self, project_id: &str) -> StoreResult<()> {
+        async_fs::create_dir_all(self.path_for(&state_prefix(project_id))?).await?;
+        Ok(())
+    }
+
+    fn describe(&self) -> String {
+        match &self.deployment_dir {
+            Some(deployment_dir) => format!("{} (templates, scripts and apps from {})", self.root.display(), deployment_dir.display()),
+            None => self.root.display().to_string(),
+        }
+    }
+}
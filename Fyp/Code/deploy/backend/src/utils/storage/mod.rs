@@ -0,0 +1,3 @@
+pub mod artifact_store;
+pub mod s3_store;
+pub mod local_store;
\ No newline at end of file
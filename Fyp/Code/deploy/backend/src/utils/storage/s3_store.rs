@@ -0,0 +1,141 @@
+use async_trait::async_trait;
+use aws_sdk_s3::types::{Delete, ObjectIdentifier};
+use aws_sdk_s3::{primitives::ByteStream, Client};
+
+use crate::terraform_handler::state_key;
+use crate::utils::storage::artifact_store::{ArtifactStore, StoreResult};
+
+// S3 accepts at most this many keys per DeleteObjects call
+const DELETE_BATCH_SIZE: usize = 1000;
+
+pub struct S3Store {
+    client: Client,
+    bucket: String,
+    region: String,
+}
+
+impl S3Store {
+    pub fn new(client: Client, bucket: &str, region: &str) -> S3Store {
+        S3Store {
+            client,
+            bucket: bucket.to_string(),
+            region: region.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl ArtifactStore for S3Store {
+    async fn list(&self, prefix: &str) -> StoreResult<Vec<String>> {
+        let mut keys = Vec::new();
+        let mut continuation_token = None;
+
+        loop {
+            let page = self
+                .client
+                .list_objects_v2()
+                .bucket(&self.bucket)
+                .prefix(prefix)
+                .set_continuation_token(continuation_token)
+                .send()
+                .await?;
+
+            keys.extend(
+                page.contents()
+                    .iter()
+                    .filter_map(|object| object.key())
+                    .filter(|key| !key.ends_with('/'))
+                    .map(String::from),
+            );
+
+            if page.is_truncated() == Some(true) {
+                continuation_token = page.next_continuation_token().map(|t| t.to_string());
+            } else {
+                break;
+            }
+        }
+
+        keys.sort();
+        Ok(keys)
+    }
+
+    async fn get(&self, key: &str) -> StoreResult<Option<Vec<u8>>> {
+        let object = match self.client.get_object().bucket(&self.bucket).key(key).send().await {
+            Ok(object) => object,
+            Err(e) if e.as_service_error().map(|e| e.is_no_such_key()).unwrap_or(false) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Some(object.body.collect().await?.into_bytes().to_vec()))
+    }
+
+    async fn put(&self, key: &str, body: Vec<u8>) -> StoreResult<()> {
+        self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .body(ByteStream::from(body))
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    async fn copy(&self, source_key: &str, destination_key: &str) -> StoreResult<()> {
+        self.client
+            .copy_object()
+            .bucket(&self.bucket)
+            .copy_source(format!("{}/{}", self.bucket, source_key))
+            .key(destination_key)
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> StoreResult<()> {
+        self.client.delete_object().bucket(&self.bucket).key(key).send().await?;
+        Ok(())
+    }
+
+    async fn delete_prefix(&self, prefix: &str) -> StoreResult<usize> {
+        let keys = self.list(prefix).await?;
+
+        for batch in keys.chunks(DELETE_BATCH_SIZE) {
+            let objects = batch
+                .iter()
+                .map(|key| ObjectIdentifier::builder().key(key).build())
+                .collect::<Result<Vec<_>, _>>()?;
+
+            self.client
+                .delete_objects()
+                .bucket(&self.bucket)
+                .delete(Delete::builder().set_objects(Some(objects)).build()?)
+                .send()
+                .await?;
+        }
+
+        Ok(keys.len())
+    }
+
+    // State lives in the bucket under the project id, with S3 lockfiles so two runs can never write it at once
+    fn terraform_backend(&self, project_id: &str) -> String {
+        format!(
+            r#"terraform {{
+  backend "s3" {{
+    bucket       = "{}"
+    key          = "{}"
+    region       = "{}"
+    encrypt      = true
+    use_lockfile = true
+  }}
+}}
+"#,
+            self.bucket,
+            state_key(project_id),
+            self.region
+        )
+    }
+
+    fn describe(&self) -> String {
+        format!("s3://{} ({})", self.bucket, self.region)
+    }
+}
@@ -0,0 +1,132 @@
+use actix_web::{web, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{deploy::ApiResponse, s3_handler::templated_files_matcher, utils::storage::artifact_store::ArtifactStore};
+
+// Every template directory under `terraform/` in the store carries one of these
+pub const MANIFEST_FILE: &str = "manifest.json";
+const TEMPLATES_PREFIX: &str = "terraform/";
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct TemplateVariable {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub var_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<serde_json::Value>,
+    #[serde(default)]
+    pub required: bool,
+    #[serde(default)]
+    pub sensitive: bool,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct TemplateManifest {
+    pub name: String,
+    pub description: String,
+    pub provider: String,
+    #[serde(default)]
+    pub variables: Vec<TemplateVariable>,
+    #[serde(default)]
+    pub outputs: Vec<String>,
+    #[serde(default)]
+    pub app_types: Vec<String>,
+    // Globs (relative to the template folder) of text files whose `__PLACEHOLDER__`s are filled in; the rest are copied as-is
+    #[serde(default)]
+    pub templated_files: Vec<String>,
+    // Variables the templated files reference as `__NAME__`; everything else only reaches Terraform through the tfvars
+    #[serde(default)]
+    pub placeholders: Vec<String>,
+}
+
+pub fn template_prefix(template: &str) -> String {
+    format!("{}{}/", TEMPLATES_PREFIX, template)
+}
+
+// Template names end up in S3 keys and local paths, so only plain directory names are accepted
+pub fn is_valid_template_name(template: &str) -> bool {
+    !template.is_empty()
+        && template
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+// Reads the manifest stored under `prefix`; None when there is no manifest there
+pub async fn read_manifest(store: &dyn ArtifactStore, prefix: &str, template: &str) -> Result<Option<TemplateManifest>, Box<dyn std::error::Error + Send + Sync>> {
+    let key = format!("{}{}", prefix, MANIFEST_FILE);
+
+    let Some(bytes) = store.get(&key).await? else {
+        return Ok(None);
+    };
+
+    let manifest: TemplateManifest = serde_json::from_slice(&bytes).map_err(|e| format!("Invalid manifest {}: {}", key, e))?;
+
+    if manifest.name != template {
+        return Err(format!("Manifest {} names template '{}'", key, manifest.name).into());
+    }
+
+    templated_files_matcher(&manifest.templated_files).map_err(|e| format!("Invalid manifest {}: {}", key, e))?;
+
+    if let Some(placeholder) = manifest.placeholders.iter().find(|name| !manifest.variables.iter().any(|variable| &variable.name == *name)) {
+        return Err(format!("Invalid manifest {}: placeholder '{}' is not a declared variable", key, placeholder).into());
+    }
+
+    Ok(Some(manifest))
+}
+
+// Returns None when the template does not exist (it has no manifest)
+pub async fn find_template_manifest(store: &dyn ArtifactStore, template: &str) -> Result<Option<TemplateManifest>, Box<dyn std::error::Error + Send + Sync>> {
+    if !is_valid_template_name(template) {
+        return Ok(None);
+    }
+
+    read_manifest(store, &template_prefix(template), template).await
+}
+
+pub async fn load_template_catalog(store: &dyn ArtifactStore) -> Result<Vec<TemplateManifest>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut names: Vec<String> = store
+        .list(TEMPLATES_PREFIX)
+        .await?
+        .iter()
+        .filter_map(|key| key.strip_prefix(TEMPLATES_PREFIX)?.split_once('/'))
+        .map(|(name, _)| name.to_string())
+        .collect();
+    names.dedup();
+
+    let mut templates = Vec::new();
+    for name in names {
+        // A broken manifest hides that one template instead of the whole catalog
+        match find_template_manifest(store, &name).await {
+            Ok(Some(manifest)) => templates.push(manifest),
+            Ok(None) => println!("⚠️ Skipping template '{}': no {}", name, MANIFEST_FILE),
+            Err(e) => eprintln!("⚠️ Skipping template '{}': {}", name, e),
+        }
+    }
+
+    templates.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(templates)
+}
+
+pub async fn list_templates(store: web::Data<dyn ArtifactStore>) -> impl Responder {
+    match load_template_catalog(store.get_ref()).await {
+        Ok(templates) => HttpResponse::Ok().json(ApiResponse {
+            status: "success".into(),
+            message: if templates.is_empty() {
+                "No templates found".into()
+            } else {
+                "Templates fetched successfully".into()
+            },
+            returneddata: Some(json!({ "templates": templates })),
+        }),
+        Err(e) => {
+            eprintln!("❌ Error listing templates: {}", e);
+            HttpResponse::InternalServerError().json(ApiResponse {
+                status: "error".into(),
+                message: "Failed to fetch templates".into(),
+                returneddata: None,
+            })
+        }
+    }
+}
+
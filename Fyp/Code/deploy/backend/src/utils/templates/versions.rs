@@ -0,0 +1,300 @@
+use std::fmt;
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use chrono::Utc;
+use mongodb::Client as MongoClient;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    app_config::AppConfig,
+    deploy::ApiResponse,
+    s3_handler::delete_specific_deployment_folder,
+    utils::database::db::{claim_template_version, complete_template_version, find_template_version, list_template_versions, release_template_version_claim},
+    utils::storage::artifact_store::ArtifactStore,
+    utils::templates::catalog::{find_template_manifest, is_valid_template_name, load_template_catalog, read_manifest, template_prefix, TemplateManifest},
+    utils::user::check_auth::require_operator,
+    utils::user::signup_func::init_mongo_client,
+};
+
+// Published versions are copied here and never written to again
+const VERSIONS_PREFIX: &str = "terraform-versions/";
+
+// What every template is published as the first time the backend starts with it
+pub const INITIAL_VERSION: &str = "1.0.0";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TemplateVersion {
+    pub name: String,
+    pub version: String,
+    // sha256 over every file in the version, so any later edit in the store is detectable
+    pub hash: String,
+    pub published_at: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PublishTemplateRequest {
+    pub version: String,
+}
+
+#[derive(Debug)]
+pub enum PublishError {
+    InvalidVersion,
+    UnknownTemplate,
+    AlreadyPublished,
+    Failed(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl fmt::Display for PublishError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PublishError::InvalidVersion => write!(f, "Versions may only contain letters, digits, '.', '-' and '_'"),
+            PublishError::UnknownTemplate => write!(f, "Template not found"),
+            PublishError::AlreadyPublished => write!(f, "This version has already been published"),
+            PublishError::Failed(e) => write!(f, "Failed to publish template version: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for PublishError {}
+
+pub fn template_version_prefix(name: &str, version: &str) -> String {
+    format!("{}{}/{}/", VERSIONS_PREFIX, name, version)
+}
+
+fn is_valid_version(version: &str) -> bool {
+    !version.is_empty()
+        && version.len() <= 64
+        && !version.starts_with('.')
+        && version
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_')
+}
+
+// Hashes relative paths and contents in key order, so the result only depends on what the files say
+pub async fn hash_template_prefix(store: &dyn ArtifactStore, prefix: &str) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let mut hasher = Sha256::new();
+
+    for key in store.list(prefix).await? {
+        let relative_path = key.strip_prefix(prefix).unwrap_or(&key);
+        let contents = store.get(&key).await?.ok_or_else(|| format!("{} disappeared while hashing", key))?;
+
+        hasher.update(relative_path.as_bytes());
+        hasher.update([0u8]);
+        hasher.update((contents.len() as u64).to_le_bytes());
+        hasher.update(&contents);
+    }
+
+    Ok(format!("sha256:{}", hex::encode(hasher.finalize())))
+}
+
+// Snapshots the live `terraform/<name>/` folder as an immutable version
+pub async fn publish_template_version(mongo_client: MongoClient, store: &dyn ArtifactStore, name: &str, version: &str) -> Result<TemplateVersion, PublishError> {
+    if !is_valid_version(version) {
+        return Err(PublishError::InvalidVersion);
+    }
+
+    match find_template_manifest(store, name).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return Err(PublishError::UnknownTemplate),
+        Err(e) => return Err(PublishError::Failed(e)),
+    }
+
+    // Claimed before any file is touched, so two publishes of one version can never write the same folder
+    match claim_template_version(mongo_client.clone(), name, version).await {
+        Ok(true) => {}
+        Ok(false) => return Err(PublishError::AlreadyPublished),
+        Err(e) => return Err(PublishError::Failed(e.into())),
+    }
+
+    let destination_prefix = template_version_prefix(name, version);
+    let published = copy_template_version(store, name, &destination_prefix).await;
+
+    let template_version = match published {
+        Ok(hash) => TemplateVersion {
+            name: name.to_string(),
+            version: version.to_string(),
+            hash,
+            published_at: Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        },
+        Err(e) => {
+            if let Err(e) = release_template_version_claim(mongo_client, name, version).await {
+                eprintln!("⚠️ Failed to release claim on {} {}: {}", name, version, e);
+            }
+            return Err(PublishError::Failed(e));
+        }
+    };
+
+    complete_template_version(mongo_client, &template_version)
+        .await
+        .map_err(|e| PublishError::Failed(e.into()))?;
+
+    println!("📌 Published template {} version {} ({})", name, version, template_version.hash);
+    println!("--------------------------------------------------------");
+
+    Ok(template_version)
+}
+
+// Copies the live template into a claimed version folder and returns the hash of what landed there
+async fn copy_template_version(store: &dyn ArtifactStore, name: &str, destination_prefix: &str) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let source_prefix = template_prefix(name);
+
+    // Anything already there is left over from a publish that never completed; the claim makes it ours to replace
+    delete_specific_deployment_folder(store, destination_prefix).await?;
+
+    for key in store.list(&source_prefix).await? {
+        let relative_path = key.strip_prefix(&source_prefix).unwrap_or(&key);
+        store.copy(&key, &format!("{}{}", destination_prefix, relative_path)).await?;
+    }
+
+    hash_template_prefix(store, destination_prefix).await
+}
+
+// Deploys need a published version, so templates that have none yet get INITIAL_VERSION at startup
+pub async fn publish_initial_versions(mongo_client: &MongoClient, store: &dyn ArtifactStore) {
+    let templates = match load_template_catalog(store).await {
+        Ok(templates) => templates,
+        Err(e) => {
+            eprintln!("❌ Failed to load templates to publish: {}", e);
+            return;
+        }
+    };
+
+    for template in templates {
+        match find_template_version(mongo_client.clone(), &template.name, None).await {
+            Ok(Some(_)) => continue,
+            Ok(None) => {}
+            Err(e) => {
+                eprintln!("❌ Failed to look up versions of {}: {}", template.name, e);
+                continue;
+            }
+        }
+
+        match publish_template_version(mongo_client.clone(), store, &template.name, INITIAL_VERSION).await {
+            Ok(_) | Err(PublishError::AlreadyPublished) => {}
+            Err(e) => eprintln!("❌ Failed to publish {} {}: {}", template.name, INITIAL_VERSION, e),
+        }
+    }
+}
+
+// Picks the requested (or latest) published version of a template and loads its manifest, shared by deploy, plan and upgrade
+pub async fn resolve_template_version(mongo_client: MongoClient, store: &dyn ArtifactStore, template: &str, version: Option<&str>) -> Result<(TemplateVersion, TemplateManifest), HttpResponse> {
+    let bad_request = |message: String| {
+        println!("❌ {}", message);
+        HttpResponse::BadRequest().json(ApiResponse {
+            status: "error".into(),
+            message,
+            returneddata: None,
+        })
+    };
+    let internal_error = |e: &dyn fmt::Display| {
+        eprintln!("❌ Error resolving template {}: {}", template, e);
+        HttpResponse::InternalServerError().json(ApiResponse {
+            status: "error".into(),
+            message: "Failed to load template version".into(),
+            returneddata: None,
+        })
+    };
+
+    if !is_valid_template_name(template) {
+        return Err(bad_request(format!("Unknown terraform_template '{}'", template)));
+    }
+
+    let template_version = match find_template_version(mongo_client, template, version).await {
+        Ok(Some(template_version)) => template_version,
+        Ok(None) => {
+            return Err(match version {
+                Some(version) => bad_request(format!("Template '{}' has no published version '{}'", template, version)),
+                None => match find_template_manifest(store, template).await {
+                    Ok(Some(_)) => bad_request(format!("Template '{}' has no published versions yet", template)),
+                    Ok(None) => bad_request(format!("Unknown terraform_template '{}'", template)),
+                    Err(e) => internal_error(&e),
+                },
+            });
+        }
+        Err(e) => return Err(internal_error(&e)),
+    };
+
+    let prefix = template_version_prefix(&template_version.name, &template_version.version);
+    match read_manifest(store, &prefix, template).await {
+        Ok(Some(manifest)) => Ok((template_version, manifest)),
+        Ok(None) => Err(internal_error(&format!("{} has no manifest", prefix))),
+        Err(e) => Err(internal_error(&e)),
+    }
+}
+
+// Refuses to use a version whose files no longer match the hash it was published with
+pub async fn verify_template_version(store: &dyn ArtifactStore, template_version: &TemplateVersion) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let prefix = template_version_prefix(&template_version.name, &template_version.version);
+    let hash = hash_template_prefix(store, &prefix).await?;
+
+    if hash != template_version.hash {
+        return Err(format!(
+            "Template {} version {} was modified after it was published (expected {}, found {})",
+            template_version.name, template_version.version, template_version.hash, hash
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+pub async fn publish_template(req: HttpRequest, app_config: web::Data<AppConfig>, store: web::Data<dyn ArtifactStore>, path: web::Path<String>, request: web::Json<PublishTemplateRequest>) -> impl Responder {
+    let name = path.into_inner();
+    println!("📥 Received publish request for template {} version {}", name, request.version);
+
+    let mongo_client = init_mongo_client().await;
+
+    // Publishing changes what every later deploy runs, so only operators may do it
+    if let Err(resp) = require_operator(&req, &mongo_client, &app_config).await {
+        return resp;
+    }
+
+    match publish_template_version(mongo_client, store.get_ref(), &name, &request.version).await {
+        Ok(template_version) => HttpResponse::Created().json(ApiResponse {
+            status: "success".into(),
+            message: format!("Published template '{}' version '{}'", name, request.version),
+            returneddata: Some(json!(template_version)),
+        }),
+        Err(e) => {
+            eprintln!("❌ Publishing {} {} failed: {}", name, request.version, e);
+            let message = e.to_string();
+            match e {
+                PublishError::InvalidVersion => HttpResponse::BadRequest(),
+                PublishError::UnknownTemplate => HttpResponse::NotFound(),
+                PublishError::AlreadyPublished => HttpResponse::Conflict(),
+                PublishError::Failed(_) => HttpResponse::InternalServerError(),
+            }
+            .json(ApiResponse {
+                status: "error".into(),
+                message,
+                returneddata: None,
+            })
+        }
+    }
+}
+
+pub async fn get_template_versions(path: web::Path<String>) -> impl Responder {
+    let name = path.into_inner();
+    let mongo_client = init_mongo_client().await;
+
+    match list_template_versions(mongo_client, &name).await {
+        Ok(versions) => HttpResponse::Ok().json(ApiResponse {
+            status: "success".into(),
+            message: if versions.is_empty() {
+                "No published versions found".into()
+            } else {
+                "Template versions fetched successfully".into()
+            },
+            returneddata: Some(json!({ "versions": versions })),
+        }),
+        Err(e) => {
+            eprintln!("❌ Error listing versions of {}: {}", name, e);
+            HttpResponse::InternalServerError().json(ApiResponse {
+                status: "error".into(),
+                message: "Failed to fetch template versions".into(),
+                returneddata: None,
+            })
+        }
+    }
+}
@@ -0,0 +1,205 @@
+use serde::Serialize;
+use std::fmt;
+
+// How many trailing stderr lines are kept on the error for context
+const STDERR_TAIL_LINES: usize = 20;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Diagnostic {
+    pub severity: String,
+    pub summary: String,
+    pub detail: String,
+}
+
+// Returned when a Terraform command exits non-zero, carrying what it printed on stderr
+#[derive(Debug, Serialize)]
+pub struct TerraformError {
+    pub command: String,
+    pub exit_status: String,
+    pub stderr_tail: Vec<String>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl TerraformError {
+    pub fn new(command: String, exit_status: String, stderr: &[String]) -> TerraformError {
+        let stderr: Vec<String> = stderr.iter().map(|line| strip_ansi(line)).collect();
+        let tail_start = stderr.len().saturating_sub(STDERR_TAIL_LINES);
+
+        TerraformError {
+            command,
+            exit_status,
+            stderr_tail: stderr[tail_start..].to_vec(),
+            diagnostics: parse_diagnostics(&stderr),
+        }
+    }
+}
+
+impl fmt::Display for TerraformError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "'{}' failed with {}", self.command, self.exit_status)?;
+
+        let errors: Vec<&str> = self
+            .diagnostics
+            .iter()
+            .filter(|d| d.severity == "error")
+            .map(|d| d.summary.as_str())
+            .collect();
+
+        if !errors.is_empty() {
+            write!(f, ": {}", errors.join("; "))?;
+        } else if let Some(last) = self.stderr_tail.iter().rev().find(|line| !line.trim().is_empty()) {
+            write!(f, ": {}", last.trim())?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for TerraformError {}
+
+// Returned before an apply when Terraform would now change something other than the plan that was reviewed
+#[derive(Debug)]
+pub struct PlanChanged;
+
+impl fmt::Display for PlanChanged {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Terraform would now make different changes than the reviewed plan, plan the upgrade again")
+    }
+}
+
+impl std::error::Error for PlanChanged {}
+
+// Drops CSI escape sequences (`ESC [ ... final byte`), which Terraform writes for colors unless it is run with -no-color
+pub fn strip_ansi(line: &str) -> String {
+    let mut stripped = String::with_capacity(line.len());
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c == '\u{1b}' && chars.peek() == Some(&'[') {
+            chars.next();
+            // Parameter and intermediate bytes run up to the final byte in '@'..='~'
+            for c in chars.by_ref() {
+                if ('@'..='~').contains(&c) {
+                    break;
+                }
+            }
+            continue;
+        }
+        stripped.push(c);
+    }
+
+    stripped
+}
+
+// Pulls `Error:` / `Warning:` blocks out of Terraform's stderr, with or without the box-drawing frame
+pub fn parse_diagnostics(stderr: &[String]) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let mut current: Option<(Diagnostic, Vec<String>)> = None;
+
+    let mut flush = |current: &mut Option<(Diagnostic, Vec<String>)>| {
+        if let Some((mut diagnostic, detail)) = current.take() {
+            diagnostic.detail = detail.join("\n").trim().to_string();
+            diagnostics.push(diagnostic);
+        }
+    };
+
+    for raw in stderr {
+        let line = raw.trim_end();
+
+        // "╷" opens and "╵" closes a framed diagnostic, "│" prefixes every line inside it
+        if line.starts_with('╵') {
+            flush(&mut current);
+            continue;
+        }
+        if line.starts_with('╷') {
+            continue;
+        }
+        let line = line.strip_prefix('│').map(|l| l.strip_prefix(' ').unwrap_or(l)).unwrap_or(line);
+
+        let header = line
+            .strip_prefix("Error: ")
+            .map(|summary| ("error", summary))
+            .or_else(|| line.strip_prefix("Warning: ").map(|summary| ("warning", summary)));
+
+        if let Some((severity, summary)) = header {
+            flush(&mut current);
+            current = Some((
+                Diagnostic {
+                    severity: severity.to_string(),
+                    summary: summary.trim().to_string(),
+                    detail: String::new(),
+                },
+                Vec::new(),
+            ));
+        } else if let Some((_, detail)) = current.as_mut() {
+            detail.push(line.to_string());
+        }
+    }
+
+    flush(&mut current);
+    diagnostics
+}
+
+// Structured failure details for an ApiResponse, when the error came from a Terraform command
+pub fn terraform_error_details(error: &(dyn std::error::Error + Send + Sync + 'static)) -> Option<serde_json::Value> {
+    error
+        .downcast_ref::<TerraformError>()
+        .and_then(|terraform_error| serde_json::to_value(terraform_error).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Captured from `terraform plan` (1.11) on a configuration referencing an undeclared resource, colors left on
+    const COLORED_STDERR: &str = include_str!("testdata/plan_undeclared_resource_color.stderr");
+    // Captured from `terraform plan -no-color` with a string passed for a number variable
+    const PLAIN_STDERR: &str = include_str!("testdata/plan_invalid_variable_no_color.stderr");
+
+    fn lines(stderr: &str) -> Vec<String> {
+        stderr.lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn strips_color_codes() {
+        assert_eq!(strip_ansi("\u{1b}[31m│\u{1b}[0m \u{1b}[0m\u{1b}[1m\u{1b}[31mError: \u{1b}[0m\u{1b}[0m\u{1b}[1mBad\u{1b}[0m"), "│ Error: Bad");
+        assert_eq!(strip_ansi("plain │ text"), "plain │ text");
+    }
+
+    #[test]
+    fn parses_colored_framed_output() {
+        let error = TerraformError::new("terraform plan".into(), "exit status: 1".into(), &lines(COLORED_STDERR));
+
+        assert_eq!(error.diagnostics.len(), 1);
+        let diagnostic = &error.diagnostics[0];
+        assert_eq!(diagnostic.severity, "error");
+        assert_eq!(diagnostic.summary, "Reference to undeclared resource");
+        assert!(diagnostic.detail.starts_with("on main.tf line 10, in output \"missing\":"), "{}", diagnostic.detail);
+        assert!(diagnostic.detail.contains("has not been declared in the\nroot module."), "{}", diagnostic.detail);
+        assert!(!diagnostic.detail.contains('\u{1b}'));
+
+        assert!(error.stderr_tail.iter().all(|line| !line.contains('\u{1b}')));
+        assert_eq!(error.to_string(), "'terraform plan' failed with exit status: 1: Reference to undeclared resource");
+    }
+
+    #[test]
+    fn parses_plain_output() {
+        let diagnostics = parse_diagnostics(&lines(PLAIN_STDERR));
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, "error");
+        assert_eq!(diagnostics[0].summary, "Invalid value for input variable");
+        assert!(diagnostics[0].detail.ends_with("a number is\nrequired."), "{}", diagnostics[0].detail);
+    }
+
+    #[test]
+    fn separates_errors_and_warnings() {
+        let stderr = lines("\nWarning: Value for undeclared variable\n\nThe root module does not declare a variable named \"extra\".\n\nError: Invalid value for input variable\n\nA number is required.\n");
+        let diagnostics = parse_diagnostics(&stderr);
+
+        let severities: Vec<&str> = diagnostics.iter().map(|d| d.severity.as_str()).collect();
+        assert_eq!(severities, ["warning", "error"]);
+        assert_eq!(diagnostics[0].detail, "The root module does not declare a variable named \"extra\".");
+        assert_eq!(diagnostics[1].detail, "A number is required.");
+    }
+}
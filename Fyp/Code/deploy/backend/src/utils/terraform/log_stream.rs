@@ -0,0 +1,76 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+
+// Lines buffered per deployment for slow subscribers before they start skipping output
+const CHANNEL_CAPACITY: usize = 512;
+
+#[derive(Clone, Debug)]
+pub enum LogEvent {
+    Line(String),
+    End,
+}
+
+// Registry of per-deployment broadcast channels that Terraform output is published on
+#[derive(Clone, Default)]
+pub struct LogHub {
+    channels: Arc<Mutex<HashMap<String, broadcast::Sender<LogEvent>>>>,
+}
+
+impl LogHub {
+    // Handle used by a running Terraform operation to publish its output
+    pub fn channel(&self, project_id: &str) -> LogChannel {
+        let sender = self
+            .channels
+            .lock()
+            .unwrap()
+            .entry(project_id.to_string())
+            .or_insert_with(|| broadcast::channel(CHANNEL_CAPACITY).0)
+            .clone();
+
+        LogChannel {
+            project_id: project_id.to_string(),
+            sender,
+            hub: self.clone(),
+        }
+    }
+
+    // None when no operation is publishing for the deployment, so a stray id never creates a channel
+    pub fn subscribe(&self, project_id: &str) -> Option<broadcast::Receiver<LogEvent>> {
+        self.channels.lock().unwrap().get(project_id).map(broadcast::Sender::subscribe)
+    }
+}
+
+pub struct LogChannel {
+    project_id: String,
+    sender: broadcast::Sender<LogEvent>,
+    hub: LogHub,
+}
+
+impl LogChannel {
+    pub fn publish(&self, line: &str) {
+        // Sending only fails when nobody is listening, which is the common case
+        let _ = self.sender.send(LogEvent::Line(line.to_string()));
+    }
+
+    // Tells subscribers the operation is over; dropping the handle then removes the channel
+    pub fn finish(self) {
+        let _ = self.sender.send(LogEvent::End);
+    }
+}
+
+// The last handle of an operation takes its channel out of the hub, even when it never called finish.
+// Subscribers then see the channel close instead of waiting on it forever
+impl Drop for LogChannel {
+    fn drop(&mut self) {
+        let mut channels = self.hub.channels.lock().unwrap();
+        let Some(sender) = channels.get(&self.project_id) else {
+            return;
+        };
+
+        // One sender is ours and one is the hub's; anything more is another handle still publishing
+        if sender.same_channel(&self.sender) && self.sender.strong_count() <= 2 {
+            channels.remove(&self.project_id);
+        }
+    }
+}
@@ -1 +1,5 @@
-pub mod terraform_handler;
\ No newline at end of file
+pub mod terraform_handler;
+pub mod log_stream;
+pub mod run_record;
+pub mod diagnostics;
+pub mod variables;
\ No newline at end of file
@@ -0,0 +1,124 @@
+use chrono::Utc;
+use mongodb::Client as MongoClient;
+use uuid::Uuid;
+
+use crate::utils::database::db::{insert_terraform_run, complete_terraform_run};
+use crate::utils::storage::artifact_store::ArtifactStore;
+use crate::utils::terraform::log_stream::LogChannel;
+
+// Collects the combined stdout/stderr of a Terraform operation while forwarding it to live subscribers
+pub struct RunLog<'a> {
+    channel: Option<&'a LogChannel>,
+    combined: Vec<String>,
+    command_stderr: Vec<String>,
+    exit_code: Option<i32>,
+}
+
+impl<'a> RunLog<'a> {
+    pub fn new(channel: &'a LogChannel) -> RunLog<'a> {
+        RunLog {
+            channel: Some(channel),
+            combined: Vec::new(),
+            command_stderr: Vec::new(),
+            exit_code: None,
+        }
+    }
+
+    // Captures output without publishing it anywhere, for one-off runs like plan previews
+    pub fn detached() -> RunLog<'static> {
+        RunLog {
+            channel: None,
+            combined: Vec::new(),
+            command_stderr: Vec::new(),
+            exit_code: None,
+        }
+    }
+
+    fn publish(&self, line: &str) {
+        if let Some(channel) = self.channel {
+            channel.publish(line);
+        }
+    }
+
+    pub fn stdout(&mut self, line: String) {
+        self.publish(&line);
+        self.combined.push(line);
+    }
+
+    pub fn stderr(&mut self, line: String) {
+        self.publish(&line);
+        self.combined.push(format!("[stderr] {}", line));
+        self.command_stderr.push(line);
+    }
+
+    // Starts a new command so its stderr can be reported on its own if it fails
+    pub fn begin_command(&mut self) {
+        self.command_stderr.clear();
+        self.exit_code = None;
+    }
+
+    pub fn command_stderr(&self) -> &[String] {
+        &self.command_stderr
+    }
+
+    // Progress markers written between commands, e.g. "Running 'terraform init'"
+    pub fn note(&mut self, line: &str) {
+        println!("{}", line);
+        self.publish(line);
+        self.combined.push(line.to_string());
+    }
+
+    pub fn set_exit_code(&mut self, code: Option<i32>) {
+        self.exit_code = code;
+    }
+
+    pub fn contents(&self) -> String {
+        let mut contents = self.combined.join("\n");
+        contents.push('\n');
+        contents
+    }
+}
+
+pub struct TerraformRun {
+    pub run_id: String,
+    pub project_id: String,
+    pub command: String,
+    pub log_key: String,
+}
+
+// Beside the deployment folder rather than inside it: that folder is Terraform's working directory, swapped out
+// on every reapply and deleted on destroy or rollback, and the logs have to outlive all of those for debugging
+pub fn run_log_key(project_id: &str, run_id: &str) -> String {
+    format!("runs/{}/{}.log", project_id, run_id)
+}
+
+pub async fn start_terraform_run(mongo_client: MongoClient, project_id: &str, command: &str) -> Result<TerraformRun, mongodb::error::Error> {
+    let run_id = Uuid::new_v4().to_string();
+    let run = TerraformRun {
+        log_key: run_log_key(project_id, &run_id),
+        run_id,
+        project_id: project_id.to_string(),
+        command: command.to_string(),
+    };
+
+    let started_at = Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    insert_terraform_run(mongo_client, &run, &started_at).await?;
+
+    println!("📝 Recording Terraform run {} ({}) for project_id: {}", run.run_id, run.command, project_id);
+    println!("--------------------------------------------------------");
+    Ok(run)
+}
+
+// Uploads the captured output and closes the run record; failures here are logged, never fatal to the operation
+pub async fn finish_terraform_run(mongo_client: MongoClient, store: &dyn ArtifactStore, run: &TerraformRun, log: &RunLog<'_>, error: Option<String>) {
+    if let Err(e) = store.put(&run.log_key, log.contents().into_bytes()).await {
+        eprintln!("⚠️ Failed to upload log for run {}: {}", run.run_id, e);
+    }
+
+    let status = if error.is_none() { "succeeded" } else { "failed" };
+    let finished_at = Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
+
+    if let Err(e) = complete_terraform_run(mongo_client, &run.run_id, status, log.exit_code, error.as_deref(), &finished_at).await {
+        eprintln!("⚠️ Failed to update run record {}: {}", run.run_id, e);
+    }
+}
@@ -2,11 +2,15 @@ use std::fs;
 use tokio::fs as async_fs;
 use std::path::PathBuf;
 use std::path::Path;
-use aws_sdk_s3::{Client, primitives::ByteStream};
 use regex::Regex;
+use sha2::{Digest, Sha256};
 use tokio::process::Command;
-use tokio::io::{AsyncBufReadExt, BufReader, AsyncWriteExt, AsyncReadExt};
-use async_recursion::async_recursion;
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+use crate::utils::storage::artifact_store::ArtifactStore;
+use crate::utils::terraform::diagnostics::{PlanChanged, TerraformError};
+use crate::utils::terraform::run_record::RunLog;
+use crate::utils::terraform::variables::{secret_env, TerraformSecrets};
 
 pub fn create_project_temp_folder(project_name: &str,project_id: &str) -> Result<PathBuf, Box<dyn std::error::Error + Send + Sync>> {
     let folder_name = format!("{} (project_id= {})", project_name, project_id);
@@ -26,9 +30,9 @@ pub fn create_project_temp_folder(project_name: &str,project_id: &str) -> Result
 }
 
 
-pub async fn download_terraform_folder_from_s3(aws_client: &Client,s3_bucket: &str,source_prefix: &str) -> Result<PathBuf, Box<dyn std::error::Error + Send + Sync>> {
+pub async fn download_terraform_folder_from_s3(store: &dyn ArtifactStore,source_prefix: &str) -> Result<PathBuf, Box<dyn std::error::Error + Send + Sync>> {
 
-    println!("📥 Downloading from S3: {}/{}", s3_bucket, source_prefix);
+    println!("📥 Downloading from store: {}", source_prefix);
     println!("--------------------------------------------------------");
 
     // Extract project name and project ID from prefix using regex
@@ -45,50 +49,32 @@ pub async fn download_terraform_folder_from_s3(aws_client: &Client,s3_bucket: &s
     println!("📁 Created local temp folder: {}", temp_dir.display());
     println!("--------------------------------------------------------");
 
-    // List objects in S3 prefix
-    let objects = aws_client
-        .list_objects_v2()
-        .bucket(s3_bucket)
-        .prefix(source_prefix)
-        .send()
-        .await?;
-
-    if let Some(contents) = objects.contents {
-        println!("🔍 Found {} objects to download", contents.len());
-        for obj in contents {
-            if let Some(key) = obj.key() {
-                let relative_path = key.strip_prefix(source_prefix).unwrap_or(key);
-                if relative_path.is_empty() {
-                    continue;
-                }
+    let keys = store.list(source_prefix).await?;
 
-                let local_path = temp_dir.join(relative_path);
-                if let Some(parent) = local_path.parent() {
-                    std::fs::create_dir_all(parent)?;
-                }
+    if keys.is_empty() {
+        println!("⚠️ No files found under prefix: {}", source_prefix);
+    } else {
+        println!("🔍 Found {} objects to download", keys.len());
+    }
 
-                println!("⬇ Downloading: {} → {}", key, local_path.display());
+    for key in &keys {
+        let relative_path = key.strip_prefix(source_prefix).unwrap_or(key);
+        if relative_path.is_empty() {
+            continue;
+        }
 
-                let obj_output = aws_client
-                    .get_object()
-                    .bucket(s3_bucket)
-                    .key(key)
-                    .send()
-                    .await?;
+        let local_path = temp_dir.join(relative_path);
+        if let Some(parent) = local_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
 
-                let mut stream: ByteStream = obj_output.body;
-                let mut file = async_fs::File::create(&local_path).await?;
+        println!("⬇ Downloading: {} → {}", key, local_path.display());
 
-                while let Some(bytes) = stream.try_next().await? {
-                    file.write_all(&bytes).await?;
-                }
+        let contents = store.get(key).await?.ok_or_else(|| format!("{} disappeared while downloading", key))?;
+        async_fs::write(&local_path, contents).await?;
 
-                println!("✅ Downloaded: {}", local_path.display());
-                println!("--------------------------------------------------------");
-            }
-        }
-    } else {
-        println!("⚠️ No files found in S3 prefix: {}", source_prefix);
+        println!("✅ Downloaded: {}", local_path.display());
+        println!("--------------------------------------------------------");
     }
 
     println!("📁 All files downloaded to: {}", temp_dir.display());
@@ -96,88 +82,184 @@ pub async fn download_terraform_folder_from_s3(aws_client: &Client,s3_bucket: &s
     Ok(temp_dir)
 }
 
-#[async_recursion]
-pub async fn upload_terraform_folder_recursive(aws_client: &Client,s3_bucket: &str,s3_prefix: &str,local_dir: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    // Walk directory recursively
-    let mut entries = async_fs::read_dir(local_dir).await?;
+pub fn state_prefix(project_id: &str) -> String {
+    format!("tfstate/{}/", project_id)
+}
 
-    while let Some(entry) = entries.next_entry().await? {
-        let path = entry.path();
+pub fn state_key(project_id: &str) -> String {
+    format!("{}terraform.tfstate", state_prefix(project_id))
+}
 
-        if path.is_dir() {
-            // Recursively upload subdirectory
-            let relative_path = path.strip_prefix(local_dir)?;
-            let new_prefix = format!(
-                "{}/{}",
-                s3_prefix.trim_end_matches('/'),
-                relative_path.to_string_lossy()
-            );
-            upload_terraform_folder_recursive(aws_client, s3_bucket, &new_prefix, &path).await?;
-        } else if path.is_file() {
-            let relative_path = path.strip_prefix(local_dir)?;
-            let s3_key = format!(
-                "{}/{}",
-                s3_prefix.trim_end_matches('/'),
-                relative_path.to_string_lossy()
-            );
-
-            println!("📤 Uploading file: {} → s3://{}/{}", path.display(), s3_bucket, s3_key);
-
-            let mut file = async_fs::File::open(&path).await?;
-            let mut contents = Vec::new();
-            file.read_to_end(&mut contents).await?;
-
-            aws_client
-                .put_object()
-                .bucket(s3_bucket)
-                .key(&s3_key)
-                .body(ByteStream::from(contents))
-                .send()
-                .await?;
+// The store decides where state lives (an S3 backend with lockfiles, or a local file)
+pub async fn upload_backend_config(store: &dyn ArtifactStore,s3_prefix: &str,project_id: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let key = format!("{}backend.tf", s3_prefix);
+    println!("📤 Writing state backend config: {}", key);
+    println!("--------------------------------------------------------");
+
+    store.put(&key, store.terraform_backend(project_id).into_bytes()).await?;
+
+    Ok(())
+}
+
+// A terraform subcommand in the working folder with the deployment's credentials in its environment.
+// Colors are off, as escape codes would end up in the run logs and break diagnostics parsing
+fn terraform_command(working_dir: &Path, secrets: &TerraformSecrets, subcommand: &str) -> Command {
+    let mut command = Command::new("terraform");
+    command.current_dir(working_dir).envs(secret_env(secrets)).arg(subcommand).arg("-no-color");
+    command
+}
+
+// Runs a single command, draining stdout and stderr together so a chatty stderr can never fill its pipe
+async fn run_command(command: &mut Command, output: &mut RunLog<'_>, prefix: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let description = describe_command(command);
+    output.begin_command();
+
+    let mut child = command.stdout(std::process::Stdio::piped()).stderr(std::process::Stdio::piped()).spawn()?;
+
+    let mut stdout_lines = BufReader::new(child.stdout.take().ok_or("Failed to capture stdout")?).lines();
+    let mut stderr_lines = BufReader::new(child.stderr.take().ok_or("Failed to capture stderr")?).lines();
+    let mut stdout_done = false;
+    let mut stderr_done = false;
+
+    while !stdout_done || !stderr_done {
+        tokio::select! {
+            line = stdout_lines.next_line(), if !stdout_done => match line? {
+                Some(line) => {
+                    println!("{} {}", prefix, line);
+                    output.stdout(line);
+                }
+                None => stdout_done = true,
+            },
+            line = stderr_lines.next_line(), if !stderr_done => match line? {
+                Some(line) => {
+                    eprintln!("{} {}", prefix, line);
+                    output.stderr(line);
+                }
+                None => stderr_done = true,
+            },
         }
     }
 
+    let status = child.wait().await?;
+    output.set_exit_code(status.code());
+    if !status.success() {
+        return Err(Box::new(TerraformError::new(description, status.to_string(), output.command_stderr())));
+    }
+
     Ok(())
 }
 
+// Runs a command whose stdout is machine-readable and returns it whole instead of streaming it
+async fn capture_command_stdout(command: &mut Command) -> Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>> {
+    let description = describe_command(command);
 
-pub async fn run_terraform_execute_commands(working_dir: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    
-    println!("🚀 Starting Terraform commands in: {}", working_dir.display());
-    println!("--------------------------------------------------------");
+    let result = command.output().await?;
+    if !result.status.success() {
+        let stderr: Vec<String> = String::from_utf8_lossy(&result.stderr).lines().map(String::from).collect();
+        return Err(Box::new(TerraformError::new(description, result.status.to_string(), &stderr)));
+    }
 
-    // Helper function to run a command and stream output line by line
-    async fn run_command(command: &mut Command) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let mut child = command.stdout(std::process::Stdio::piped()).stderr(std::process::Stdio::piped()).spawn()?;
-        
-        if let Some(stdout) = &mut child.stdout {
-            let reader = BufReader::new(stdout);
-            let mut lines = reader.lines();
-
-            while let Some(line) = lines.next_line().await? {
-                println!("🌱 {}", line);
-            }
-        }
-        
-        let status = child.wait().await?;
-        if !status.success() {
-            return Err(format!("Command failed with status: {}", status).into());
+    Ok(result.stdout)
+}
+
+fn describe_command(command: &Command) -> String {
+    let command = command.as_std();
+    std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|part| part.to_string_lossy().into_owned())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Reads `terraform output -json` into name → value, leaving out anything marked sensitive
+pub async fn read_terraform_outputs(working_dir: &Path) -> Result<serde_json::Map<String, serde_json::Value>, Box<dyn std::error::Error + Send + Sync>> {
+    let stdout = capture_command_stdout(terraform_command(working_dir, &TerraformSecrets::new(), "output").arg("-json")).await?;
+    let raw: serde_json::Map<String, serde_json::Value> = serde_json::from_slice(&stdout)?;
+
+    let outputs = raw
+        .into_iter()
+        .filter(|(_, output)| output["sensitive"].as_bool() != Some(true))
+        .map(|(name, output)| (name, output["value"].clone()))
+        .collect();
+
+    Ok(outputs)
+}
+
+// Every template must declare these so the dashboard can tell users where their server is
+pub const REQUIRED_OUTPUTS: [&str; 2] = ["ipv4_address", "server_id"];
+
+pub fn verify_required_outputs(working_dir: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let output_re = Regex::new(r#"(?m)^\s*output\s+"([^"]+)""#)?;
+    let mut declared = Vec::new();
+
+    for entry in fs::read_dir(working_dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("tf") {
+            continue;
         }
+        let contents = fs::read_to_string(&path)?;
+        declared.extend(output_re.captures_iter(&contents).map(|caps| caps[1].to_string()));
+    }
+
+    let missing: Vec<&str> = REQUIRED_OUTPUTS
+        .iter()
+        .filter(|name| !declared.iter().any(|declared| declared == *name))
+        .copied()
+        .collect();
+
+    if !missing.is_empty() {
+        return Err(format!("Template does not declare required output(s): {}", missing.join(", ")).into());
+    }
+
+    Ok(())
+}
 
-        Ok(())
+// Destroy in a folder without the deployment's configuration still "succeeds" against an empty local state,
+// which would orphan the live server, so refuse unless the folder points at this deployment's real backend
+pub fn verify_destroy_config(working_dir: &Path, expected_backend: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let backend = fs::read_to_string(working_dir.join("backend.tf")).map_err(|_| "Deployment folder has no backend.tf, refusing to destroy")?;
+    if backend != expected_backend {
+        return Err("Deployment folder's backend.tf does not point at this deployment's state, refusing to destroy".into());
     }
 
+    let has_configuration = fs::read_dir(working_dir)?.filter_map(Result::ok).any(|entry| {
+        let path = entry.path();
+        path.extension().and_then(|ext| ext.to_str()) == Some("tf") && path.file_name().and_then(|name| name.to_str()) != Some("backend.tf")
+    });
+    if !has_configuration {
+        return Err("Deployment folder has no Terraform configuration, refusing to destroy".into());
+    }
+
+    Ok(())
+}
+
+// Applies exactly the plan it makes; with `reviewed_plan` set, that plan must match the fingerprint of the one the user reviewed
+pub async fn run_terraform_execute_commands(working_dir: &Path, secrets: &TerraformSecrets, reviewed_plan: Option<&str>, output: &mut RunLog<'_>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    
+    println!("🚀 Starting Terraform commands in: {}", working_dir.display());
+    println!("--------------------------------------------------------");
+
     // terraform init
-    println!("🔧 Running 'terraform init'...");
-    run_command(Command::new("terraform").arg("init").current_dir(working_dir)).await?;
+    output.note("🔧 Running 'terraform init'...");
+    run_command(terraform_command(working_dir, secrets, "init").arg("-input=false"), output, "🌱").await?;
 
     // terraform plan -out=tfplan
-    println!("🔧 Running 'terraform plan'...");
-    run_command(Command::new("terraform").arg("plan").current_dir(working_dir)).await?;
+    output.note("🔧 Running 'terraform plan -out=tfplan'...");
+    run_command(terraform_command(working_dir, secrets, "plan").arg("-input=false").arg("-out=tfplan"), output, "🌱").await?;
+
+    if let Some(reviewed_plan) = reviewed_plan {
+        output.note("🔧 Comparing the plan with the reviewed one...");
+        let stdout = capture_command_stdout(terraform_command(working_dir, secrets, "show").arg("-json").arg("tfplan")).await?;
+        let plan: serde_json::Value = serde_json::from_slice(&stdout)?;
+        if plan_fingerprint(&plan) != reviewed_plan {
+            output.note("❌ The plan differs from the reviewed one, nothing was applied");
+            return Err(Box::new(PlanChanged));
+        }
+    }
 
-    // terraform apply -auto-approve
-    println!("🔧 Running 'terraform apply'...");
-    run_command(Command::new("terraform").arg("apply").arg("-auto-approve").current_dir(working_dir)).await?;
+    // terraform apply tfplan (a saved plan is applied as-is, without prompting)
+    output.note("🔧 Running 'terraform apply tfplan'...");
+    run_command(terraform_command(working_dir, secrets, "apply").arg("-input=false").arg("tfplan"), output, "🌱").await?;
 
     println!("✅ Terraform commands completed successfully.");
     println!("--------------------------------------------------------");
@@ -185,34 +267,65 @@ pub async fn run_terraform_execute_commands(working_dir: &Path) -> Result<(), Bo
     Ok(())
 }
 
+// Hashes what a plan would change to each resource, so a later plan can be checked against the one that was reviewed
+pub fn plan_fingerprint(plan: &serde_json::Value) -> String {
+    let changes: Vec<serde_json::Value> = plan["resource_changes"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|change| {
+            serde_json::json!({
+                "address": change["address"],
+                "actions": change["change"]["actions"],
+                "before": change["change"]["before"],
+                "after": change["change"]["after"],
+                "after_unknown": change["change"]["after_unknown"],
+            })
+        })
+        .collect();
+
+    let digest = Sha256::digest(serde_json::Value::Array(changes).to_string().as_bytes());
+    format!("sha256:{}", hex::encode(digest))
+}
+
+
+// Runs init and a saved plan, then returns `terraform show -json` of that plan without applying anything
+pub async fn run_terraform_plan_commands(working_dir: &Path, secrets: &TerraformSecrets, output: &mut RunLog<'_>) -> Result<serde_json::Value, Box<dyn std::error::Error + Send + Sync>> {
+
+    println!("🔎 Planning Terraform changes in: {}", working_dir.display());
+    println!("--------------------------------------------------------");
 
-pub async fn run_destroy_command(working_dir: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // terraform init
+    output.note("🔧 Running 'terraform init'...");
+    run_command(terraform_command(working_dir, secrets, "init").arg("-input=false"), output, "🌱").await?;
+
+    // terraform plan -out=tfplan
+    output.note("🔧 Running 'terraform plan -out=tfplan'...");
+    run_command(terraform_command(working_dir, secrets, "plan").arg("-input=false").arg("-out=tfplan"), output, "🌱").await?;
+
+    // terraform show -json tfplan (stdout is a single JSON document, so it is captured rather than streamed)
+    output.note("🔧 Running 'terraform show -json tfplan'...");
+    let stdout = capture_command_stdout(terraform_command(working_dir, secrets, "show").arg("-json").arg("tfplan")).await?;
+    let plan: serde_json::Value = serde_json::from_slice(&stdout)?;
+    println!("✅ Terraform plan completed successfully.");
+    println!("--------------------------------------------------------");
+
+    Ok(plan)
+}
+
+
+pub async fn run_destroy_command(working_dir: &Path, secrets: &TerraformSecrets, output: &mut RunLog<'_>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     
     println!("🔧 Running 'terraform destroy' in: {}", working_dir.display());
     println!("--------------------------------------------------------");
 
-    let mut child = Command::new("terraform")
-        .arg("destroy")
-        .arg("-auto-approve")
-        .current_dir(working_dir)
-        .stdout(std::process::Stdio::piped())
-        .stderr(std::process::Stdio::piped())
-        .spawn()?;
-
-    if let Some(stdout) = &mut child.stdout {
-        let reader = BufReader::new(stdout);
-        let mut lines = reader.lines();
-
-        while let Some(line) = lines.next_line().await? {
-            println!("🔥 {}", line);
-            println!("--------------------------------------------------------");
-        }
-    }
+    // Only the rendered configuration is kept, so providers and the state backend have to be initialised again
+    output.note("🔧 Running 'terraform init'...");
+    run_command(terraform_command(working_dir, secrets, "init").arg("-input=false"), output, "🔥").await?;
 
-    let status = child.wait().await?;
-    if !status.success() {
-        return Err(format!("Terraform destroy failed with status: {}", status).into());
-    }
+    output.note("🔧 Running 'terraform destroy'...");
+
+    run_command(terraform_command(working_dir, secrets, "destroy").arg("-auto-approve"), output, "🔥").await?;
 
     println!("✅ Terraform destroy completed successfully.");
     println!("--------------------------------------------------------");
@@ -229,34 +342,48 @@ pub async fn run_destroy_command(working_dir: &Path) -> Result<(), Box<dyn std::
     Ok(())
 }
 
-pub async fn execute_deployment(aws_client: &Client,s3_bucket: &str,s3_prefix: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+pub async fn execute_deployment(store: &dyn ArtifactStore,s3_prefix: &str,project_id: &str,secrets: &TerraformSecrets,reviewed_plan: Option<&str>,output: &mut RunLog<'_>) -> Result<serde_json::Map<String, serde_json::Value>, Box<dyn std::error::Error + Send + Sync>> {
     println!("🔄 Starting deployment execution flow for: {}", s3_prefix);
     println!("--------------------------------------------------------");
 
     // Step 1: Download the Terraform folder from S3 to local temp folder
-    let local_dir = download_terraform_folder_from_s3(aws_client, s3_bucket, s3_prefix).await?;
+    let local_dir = download_terraform_folder_from_s3(store, s3_prefix).await?;
     println!("✅ Downloaded Terraform folder locally at: {}", local_dir.display());
     println!("--------------------------------------------------------");
 
-    // Step 2: Run Terraform commands (init, plan, apply)
-    if let Err(e) = run_terraform_execute_commands(&local_dir).await {
-        eprintln!("❌ Deployment execution error: {}", e);
-        println!("--------------------------------------------------------");
+    // Refuse templates that would leave us without an address for the server, before anything is created
+    if let Err(e) = verify_required_outputs(&local_dir) {
+        eprintln!("❌ {}", e);
+        if let Err(e) = async_fs::remove_dir_all(&local_dir).await {
+            eprintln!("⚠️ Failed to delete local temp folder {}: {}", local_dir.display(), e);
+        }
         return Err(e);
     }
 
-    // Step 3: Upload terraform.tfstate and .terraform.lock.hcl to same S3 folder
-    println!("📤 Uploading terraform outputs (.tfstate + .lock.hcl) to S3: {}", s3_prefix);
-    println!("--------------------------------------------------------");
-
-    if let Err(e) = upload_terraform_folder_recursive(aws_client, s3_bucket, s3_prefix, &local_dir).await {
-        eprintln!("❌ Upload error: {}", e);
+    // Step 2: Run Terraform commands (init, plan, apply)
+    if let Err(e) = store.prepare_terraform_state(project_id).await {
+        eprintln!("❌ Failed to prepare state for {}: {}", project_id, e);
+        if let Err(e) = async_fs::remove_dir_all(&local_dir).await {
+            eprintln!("⚠️ Failed to delete local temp folder {}: {}", local_dir.display(), e);
+        }
+        return Err(e);
+    }
+    if let Err(e) = run_terraform_execute_commands(&local_dir, secrets, reviewed_plan, output).await {
+        eprintln!("❌ Deployment execution error: {}", e);
         println!("--------------------------------------------------------");
         return Err(e);
     }
 
-    println!("✅ Output files uploaded successfully to S3.");
-    println!("--------------------------------------------------------");
+    // Step 3: Read the template's outputs (server IP, id, ...) through the remote state
+    output.note("🔧 Running 'terraform output -json'...");
+    let outputs = match read_terraform_outputs(&local_dir).await {
+        Ok(outputs) => outputs,
+        Err(e) => {
+            // The infrastructure is up and its state is in the backend, so a missing output is not worth failing the deployment
+            eprintln!("⚠️ Failed to read Terraform outputs: {}", e);
+            serde_json::Map::new()
+        }
+    };
 
     // Step 4: Clean up local folder
     println!("🧹 Cleaning up local temp folder: {}", local_dir.display());
@@ -266,22 +393,37 @@ pub async fn execute_deployment(aws_client: &Client,s3_bucket: &str,s3_prefix: &
         eprintln!("⚠️ Failed to delete local temp folder {}: {}", local_dir.display(), e);
     }
 
-    Ok(())
+    Ok(outputs)
 }
 
 
-pub async fn destroy_terraform_resources(aws_client: &Client,s3_bucket: &str,s3_prefix: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+pub async fn destroy_terraform_resources(store: &dyn ArtifactStore,s3_prefix: &str,project_id: &str,secrets: &TerraformSecrets,output: &mut RunLog<'_>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     
     println!("🧨 Starting Terraform destroy flow for: {}", s3_prefix);
     println!("--------------------------------------------------------");
 
     // Step 1: Download Terraform folder from S3
-    let local_dir = download_terraform_folder_from_s3(aws_client, s3_bucket, s3_prefix).await?;
+    let local_dir = download_terraform_folder_from_s3(store, s3_prefix).await?;
     println!("✅ Downloaded Terraform folder locally at: {}", local_dir.display());
     println!("--------------------------------------------------------");
 
+    if let Err(e) = verify_destroy_config(&local_dir, &store.terraform_backend(project_id)) {
+        output.note(&format!("❌ {}", e));
+        if let Err(e) = async_fs::remove_dir_all(&local_dir).await {
+            eprintln!("⚠️ Failed to delete local temp folder {}: {}", local_dir.display(), e);
+        }
+        return Err(e);
+    }
+
     // Step 2: Run terraform destroy
-    if let Err(e) = run_destroy_command(&local_dir).await {
+    if let Err(e) = store.prepare_terraform_state(project_id).await {
+        eprintln!("❌ Failed to prepare state for {}: {}", project_id, e);
+        if let Err(e) = async_fs::remove_dir_all(&local_dir).await {
+            eprintln!("⚠️ Failed to delete local temp folder {}: {}", local_dir.display(), e);
+        }
+        return Err(e);
+    }
+    if let Err(e) = run_destroy_command(&local_dir, secrets, output).await {
         eprintln!("❌ Terraform destroy error: {}", e);
         println!("--------------------------------------------------------");
         return Err(e);
@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+use std::path::Path;
+use serde_json::{Map, Value};
+
+use crate::utils::storage::artifact_store::ArtifactStore;
+use crate::utils::templates::catalog::TemplateManifest;
+
+// Terraform loads this automatically, so templates need no placeholder defaults for per-deployment values
+pub const TFVARS_FILE: &str = "terraform.tfvars.json";
+
+// Sensitive variable name → value; never written to a file, only handed to Terraform as TF_VAR_* at run time
+pub type TerraformSecrets = HashMap<String, String>;
+
+pub fn secret_env(secrets: &TerraformSecrets) -> impl Iterator<Item = (String, &str)> {
+    secrets.iter().map(|(name, value)| (format!("TF_VAR_{}", name), value.as_str()))
+}
+
+// Loosely mirrors Terraform's type constraints; collection types only check the JSON shape
+fn matches_type(var_type: &str, value: &Value) -> bool {
+    let var_type = var_type.trim();
+    match var_type {
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "bool" => value.is_boolean(),
+        "any" => true,
+        _ if var_type.starts_with("list(") || var_type.starts_with("set(") || var_type.starts_with("tuple(") => value.is_array(),
+        _ if var_type.starts_with("map(") || var_type.starts_with("object(") => value.is_object(),
+        _ => false,
+    }
+}
+
+// Checks the request's values against what the template declares and returns the full set to write.
+// Only the names of the secrets are needed, so credentials can stay sealed until Terraform runs
+pub fn validate_variables(manifest: &TemplateManifest, values: &Map<String, Value>, secret_names: &[&str]) -> Result<Map<String, Value>, String> {
+    let mut resolved = Map::new();
+
+    for name in values.keys().map(String::as_str).chain(secret_names.iter().copied()) {
+        if !manifest.variables.iter().any(|variable| variable.name == name) {
+            return Err(format!("Template '{}' does not declare variable '{}'", manifest.name, name));
+        }
+    }
+
+    for variable in &manifest.variables {
+        // A secret stays out of the tfvars even where an older template version does not mark it sensitive, as the file would win over TF_VAR_*
+        if variable.sensitive || secret_names.contains(&variable.name.as_str()) {
+            if values.contains_key(&variable.name) {
+                return Err(format!("Variable '{}' is sensitive and cannot be passed as a plain value", variable.name));
+            }
+            if variable.required && !secret_names.contains(&variable.name.as_str()) {
+                return Err(format!("Missing credential for required variable '{}'", variable.name));
+            }
+            continue;
+        }
+
+        let value = match values.get(&variable.name).or(variable.default.as_ref()) {
+            Some(value) => value,
+            None if variable.required => return Err(format!("Missing value for required variable '{}'", variable.name)),
+            None => continue,
+        };
+
+        if !matches_type(&variable.var_type, value) {
+            return Err(format!("Variable '{}' must be of type {}, got {}", variable.name, variable.var_type, value));
+        }
+
+        resolved.insert(variable.name.clone(), value.clone());
+    }
+
+    Ok(resolved)
+}
+
+// Makes a value safe to sit between the quotes of an HCL string: it can neither close the string
+// nor start a `${}` interpolation or `%{}` directive
+pub fn escape_hcl_string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            '$' | '%' if chars.peek() == Some(&'{') => {
+                escaped.push(c);
+                escaped.push(c);
+            }
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// The variables the manifest lists as placeholders, keyed `__NAME__` for templated files; `.tf` files get them HCL-escaped
+pub fn placeholder_replacements(manifest: &TemplateManifest, values: &Map<String, Value>) -> HashMap<String, String> {
+    values
+        .iter()
+        .filter(|(name, _)| manifest.placeholders.contains(name))
+        .filter_map(|(name, value)| {
+            value
+                .as_str()
+                .map(|value| (format!("__{}__", name.to_uppercase().replace('-', "_")), value.to_string()))
+        })
+        .collect()
+}
+
+pub fn render_tfvars(values: &Map<String, Value>) -> Result<Vec<u8>, serde_json::Error> {
+    serde_json::to_vec_pretty(values)
+}
+
+pub async fn upload_tfvars(store: &dyn ArtifactStore, s3_prefix: &str, values: &Map<String, Value>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let key = format!("{}{}", s3_prefix, TFVARS_FILE);
+    println!("📤 Writing Terraform variables: {}", key);
+    println!("--------------------------------------------------------");
+
+    store.put(&key, render_tfvars(values)?).await?;
+
+    Ok(())
+}
+
+pub async fn write_tfvars(local_dir: &Path, values: &Map<String, Value>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    tokio::fs::write(local_dir.join(TFVARS_FILE), render_tfvars(values)?).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::escape_hcl_string;
+
+    #[test]
+    fn escapes_quotes_backslashes_and_newlines() {
+        assert_eq!(escape_hcl_string(r#"a"b\c"#), r#"a\"b\\c"#);
+        assert_eq!(escape_hcl_string("line1\nline2\r\tx"), r"line1\nline2\r\tx");
+    }
+
+    #[test]
+    fn escapes_interpolation_and_directives() {
+        assert_eq!(escape_hcl_string("${file(\"/etc/passwd\")}"), r#"$${file(\"/etc/passwd\")}"#);
+        assert_eq!(escape_hcl_string("%{ if true }x%{ endif }"), "%%{ if true }x%%{ endif }");
+    }
+
+    #[test]
+    fn leaves_lone_dollar_and_percent_alone() {
+        assert_eq!(escape_hcl_string("cost $5 at 50%"), "cost $5 at 50%");
+        assert_eq!(escape_hcl_string("node-1.example.com"), "node-1.example.com");
+    }
+}
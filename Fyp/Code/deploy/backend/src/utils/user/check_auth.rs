@@ -6,6 +6,7 @@ use mongodb::bson::Document;
 
 /// Simulate session lookup — replace this with real logic
 use crate::utils::database::db::{validate_session};
+use crate::app_config::AppConfig;
 
 
 /// Simulate getting user data from MongoDB
@@ -18,18 +19,43 @@ async fn get_user_by_email(
     collection.find_one(doc! { "email": email }, None).await
 }
 
-pub async fn check_auth(
-    req: HttpRequest,
-    mongo: web::Data<Client>,
-) -> HttpResponse {
-    // Try both authentication methods
-    let token = req.cookie("session_id")
+// The session cookie, or the header for clients that cannot keep cookies
+fn session_token(req: &HttpRequest) -> Option<String> {
+    req.cookie("session_id")
         .map(|c| c.value().to_string())
         .or_else(|| {
             req.headers()
                 .get("X-Session-Token")
                 .and_then(|h| h.to_str().ok().map(String::from))
-        });
+        })
+}
+
+// The signed-in user must be one of the configured operators
+pub async fn require_operator(req: &HttpRequest, mongo: &Client, app_config: &AppConfig) -> Result<String, HttpResponse> {
+    let Some(token) = session_token(req) else {
+        return Err(unauthorized_response());
+    };
+    let Some(email) = validate_session(&token, mongo).await else {
+        return Err(unauthorized_response());
+    };
+
+    if !app_config.operators.iter().any(|operator| operator.eq_ignore_ascii_case(&email)) {
+        println!("🚫 {} is not an operator", email);
+        return Err(HttpResponse::Forbidden().json(json!({
+            "message": "Only operators can do this",
+            "code": "FORBIDDEN"
+        })));
+    }
+
+    Ok(email)
+}
+
+pub async fn check_auth(
+    req: HttpRequest,
+    mongo: web::Data<Client>,
+) -> HttpResponse {
+    // Try both authentication methods
+    let token = session_token(&req);
 
     println!("🔐 Check-Auth Called");
     println!("   Token: {:?}", token);
@@ -72,17 +72,17 @@ pub async fn handle_signup(data: web::Json<SignupRequest>) -> impl Responder {
                         .secure(true) // set to true if using HTTPS
                         .finish();
 
-                    return HttpResponse::Ok()
+                    HttpResponse::Ok()
                         .cookie(session_cookie)
                         .append_header(("X-Session-Token", session_token))
                         .json(ApiResponse {
                             status: "success".to_string(),
                             message: "Signup and login successful".to_string(),
                             returneddata: Some(response_data),
-                        });
+                        })
                 }
                 Err(err_response) => {
-                    return HttpResponse::Unauthorized().json(err_response);
+                    HttpResponse::Unauthorized().json(err_response)
                 }
             }
         }
@@ -0,0 +1,97 @@
+locals {
+  # Security groups take numbered protocols, port ranges as from/to and CIDRs split by IP version
+  ingress_rules = [
+    for rule in var.firewall_rules : {
+      protocol    = lookup({ gre = "47", esp = "50" }, rule.protocol, rule.protocol)
+      from_port   = rule.port == null ? (rule.protocol == "icmp" ? -1 : 0) : tonumber(split("-", rule.port)[0])
+      to_port     = rule.port == null ? (rule.protocol == "icmp" ? -1 : 0) : tonumber(reverse(split("-", rule.port))[0])
+      cidr_blocks = [for ip in rule.source_ips : strcontains(ip, "/") ? ip : "${ip}/32" if !strcontains(ip, ":")]
+      # ICMP over IPv6 is a different protocol (icmpv6) on AWS
+      ipv6_cidr_blocks = rule.protocol == "icmp" ? [] : [for ip in rule.source_ips : strcontains(ip, "/") ? ip : "${ip}/128" if strcontains(ip, ":")]
+      description      = rule.description
+    } if rule.direction == "in"
+  ]
+}
+
+data "aws_ami" "debian" {
+  most_recent = true
+  owners      = ["136693071363"]
+
+  filter {
+    name   = "name"
+    values = ["debian-12-amd64-*"]
+  }
+
+  filter {
+    name   = "architecture"
+    values = ["x86_64"]
+  }
+}
+
+resource "aws_security_group" "app-firewall" {
+  name = "${var.node-name}-firewall"
+
+  dynamic "ingress" {
+    for_each = local.ingress_rules
+    content {
+      protocol         = ingress.value.protocol
+      from_port        = ingress.value.from_port
+      to_port          = ingress.value.to_port
+      cidr_blocks      = ingress.value.cidr_blocks
+      ipv6_cidr_blocks = ingress.value.ipv6_cidr_blocks
+      description      = ingress.value.description
+    }
+  }
+
+  egress {
+    protocol         = "-1"
+    from_port        = 0
+    to_port          = 0
+    cidr_blocks      = ["0.0.0.0/0"]
+    ipv6_cidr_blocks = ["::/0"]
+  }
+}
+
+resource "aws_key_pair" "app-key" {
+  count      = var.ssh_public_key == "" ? 0 : 1
+  key_name   = "${var.node-name}-key"
+  public_key = var.ssh_public_key
+}
+
+resource "aws_instance" "app-server" {
+  ami                    = data.aws_ami.debian.id
+  instance_type          = var.instance_type
+  user_data              = var.user_data == "" ? null : var.user_data
+  key_name               = var.ssh_public_key == "" ? null : aws_key_pair.app-key[0].key_name
+  vpc_security_group_ids = [aws_security_group.app-firewall.id]
+
+  tags = {
+    Name = var.node-name
+  }
+}
+
+resource "aws_ebs_volume" "app-volume" {
+  availability_zone = aws_instance.app-server.availability_zone
+  size              = var.volume_size
+  type              = "gp3"
+
+  tags = {
+    Name = "${var.node-name}-volume"
+  }
+}
+
+resource "aws_volume_attachment" "app-volume" {
+  device_name = "/dev/sdf"
+  volume_id   = aws_ebs_volume.app-volume.id
+  instance_id = aws_instance.app-server.id
+}
+
+resource "aws_eip" "app-ip" {
+  count    = var.reserved_ip ? 1 : 0
+  domain   = "vpc"
+  instance = aws_instance.app-server.id
+
+  tags = {
+    Name = "${var.node-name}-ip"
+  }
+}
@@ -0,0 +1,11 @@
+output "ipv4_address" {
+  value = var.reserved_ip ? aws_eip.app-ip[0].public_ip : aws_instance.app-server.public_ip
+}
+
+output "server_id" {
+  value = aws_instance.app-server.id
+}
+
+output "volume_id" {
+  value = aws_ebs_volume.app-volume.id
+}
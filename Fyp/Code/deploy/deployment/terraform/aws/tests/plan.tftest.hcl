@@ -0,0 +1,69 @@
+# Plan-only check of the variables the backend generates. tests/backend.tfvars.json is what the backend writes for a
+# t3.small in eu-central-1 with a reserved IP; a cargo test keeps it in sync, so run with
+# `terraform init && terraform test -var-file=tests/backend.tfvars.json`.
+# The AWS provider is mocked, so no credentials or account are needed.
+mock_provider "aws" {
+  mock_data "aws_ami" {
+    defaults = {
+      id = "ami-0123456789abcdef0"
+    }
+  }
+}
+
+# The credentials never reach the tfvars, the backend passes them as TF_VAR_*
+variables {
+  aws_access_key_id     = "AKIAEXAMPLE"
+  aws_secret_access_key = "secret"
+}
+
+run "default_rules" {
+  command = plan
+
+  assert {
+    condition     = aws_instance.app-server.instance_type == "t3.small" && aws_instance.app-server.ami == "ami-0123456789abcdef0"
+    error_message = "Instance does not use the requested type and the Debian AMI"
+  }
+
+  assert {
+    condition     = aws_ebs_volume.app-volume.size == 20 && aws_ebs_volume.app-volume.type == "gp3"
+    error_message = "EBS volume does not have the requested size"
+  }
+
+  assert {
+    condition     = length(aws_eip.app-ip) == 1 && length(aws_key_pair.app-key) == 0
+    error_message = "Expected an Elastic IP and no key pair"
+  }
+
+  assert {
+    condition     = length(aws_security_group.app-firewall.ingress) == 3
+    error_message = "Expected the ICMP, HTTP and HTTPS default rules"
+  }
+}
+
+run "app_rules" {
+  command = plan
+
+  variables {
+    reserved_ip    = false
+    ssh_public_key = "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIEXAMPLEKEYEXAMPLEKEYEXAMPLEKEYEXAMPLE demo"
+    firewall_rules = [
+      { protocol = "icmp" },
+      { protocol = "udp", port = "51820", source_ips = ["203.0.113.7"] },
+      { protocol = "tcp", port = "8000-8100", source_ips = ["2001:db8::/32"] },
+    ]
+  }
+
+  assert {
+    condition     = length(aws_eip.app-ip) == 0 && length(aws_key_pair.app-key) == 1
+    error_message = "Expected a key pair and no Elastic IP"
+  }
+
+  assert {
+    condition = jsonencode(local.ingress_rules) == jsonencode([
+      { protocol = "icmp", from_port = -1, to_port = -1, cidr_blocks = ["0.0.0.0/0"], ipv6_cidr_blocks = [], description = null },
+      { protocol = "udp", from_port = 51820, to_port = 51820, cidr_blocks = ["203.0.113.7/32"], ipv6_cidr_blocks = [], description = null },
+      { protocol = "tcp", from_port = 8000, to_port = 8100, cidr_blocks = [], ipv6_cidr_blocks = ["2001:db8::/32"], description = null },
+    ])
+    error_message = "Firewall rules were not translated into security group rules"
+  }
+}
@@ -0,0 +1,69 @@
+resource "digitalocean_firewall" "app-firewall" {
+  name        = "${var.node-name}-firewall"
+  droplet_ids = [digitalocean_droplet.app-server.id]
+
+  dynamic "inbound_rule" {
+    for_each = [for rule in var.firewall_rules : rule if rule.direction == "in"]
+    content {
+      protocol         = inbound_rule.value.protocol
+      port_range       = inbound_rule.value.port
+      source_addresses = inbound_rule.value.source_ips
+    }
+  }
+
+  # Droplet firewalls drop everything that is not allowed, outbound traffic included
+  dynamic "outbound_rule" {
+    for_each = ["tcp", "udp"]
+    content {
+      protocol              = outbound_rule.value
+      port_range            = "all"
+      destination_addresses = ["0.0.0.0/0", "::/0"]
+    }
+  }
+
+  outbound_rule {
+    protocol              = "icmp"
+    destination_addresses = ["0.0.0.0/0", "::/0"]
+  }
+
+  lifecycle {
+    precondition {
+      condition     = alltrue([for rule in var.firewall_rules : contains(["tcp", "udp", "icmp"], rule.protocol)])
+      error_message = "DigitalOcean firewalls only support tcp, udp and icmp rules."
+    }
+  }
+}
+
+resource "digitalocean_ssh_key" "app-key" {
+  count      = var.ssh_public_key == "" ? 0 : 1
+  name       = "${var.node-name}-key"
+  public_key = var.ssh_public_key
+}
+
+resource "digitalocean_droplet" "app-server" {
+  name      = var.node-name
+  size      = var.size
+  image     = var.image
+  region    = var.region
+  user_data = var.user_data == "" ? null : var.user_data
+  ssh_keys  = digitalocean_ssh_key.app-key[*].fingerprint
+  ipv6      = true
+}
+
+resource "digitalocean_volume" "app-volume" {
+  name                    = "${var.node-name}-volume"
+  region                  = var.region
+  size                    = var.volume_size
+  initial_filesystem_type = "ext4"
+}
+
+resource "digitalocean_volume_attachment" "app-volume" {
+  droplet_id = digitalocean_droplet.app-server.id
+  volume_id  = digitalocean_volume.app-volume.id
+}
+
+resource "digitalocean_reserved_ip" "app-ip" {
+  count      = var.reserved_ip ? 1 : 0
+  region     = var.region
+  droplet_id = digitalocean_droplet.app-server.id
+}
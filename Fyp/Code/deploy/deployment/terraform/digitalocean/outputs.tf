@@ -0,0 +1,15 @@
+output "ipv4_address" {
+  value = var.reserved_ip ? digitalocean_reserved_ip.app-ip[0].ip_address : digitalocean_droplet.app-server.ipv4_address
+}
+
+output "ipv6_address" {
+  value = digitalocean_droplet.app-server.ipv6_address
+}
+
+output "server_id" {
+  value = digitalocean_droplet.app-server.id
+}
+
+output "volume_id" {
+  value = digitalocean_volume.app-volume.id
+}
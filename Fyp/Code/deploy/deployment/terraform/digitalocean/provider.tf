@@ -0,0 +1,15 @@
+terraform {
+  # The generated S3 backend locks state with use_lockfile, which older releases reject
+  required_version = ">= 1.10"
+
+  required_providers {
+    digitalocean = {
+      source  = "digitalocean/digitalocean"
+      version = "~> 2.0"
+    }
+  }
+}
+
+provider "digitalocean" {
+  token = var.do_token
+}
@@ -0,0 +1,68 @@
+# Plan-only check of the template against hand-written variables shaped like the backend's; run with `terraform init && terraform test`.
+# The DigitalOcean provider is mocked, so no token or account is needed.
+mock_provider "digitalocean" {}
+
+variables {
+  do_token    = "dop_v1_example"
+  node-name   = "demo-server"
+  size        = "s-1vcpu-2gb"
+  region      = "fra1"
+  volume_size = 20
+}
+
+run "default_rules" {
+  command = plan
+
+  assert {
+    condition     = digitalocean_droplet.app-server.size == "s-1vcpu-2gb" && digitalocean_droplet.app-server.region == "fra1"
+    error_message = "Droplet does not use the requested size and region"
+  }
+
+  assert {
+    condition     = digitalocean_volume.app-volume.size == 20 && digitalocean_volume.app-volume.region == "fra1"
+    error_message = "Volume does not have the requested size"
+  }
+
+  assert {
+    condition     = length(digitalocean_reserved_ip.app-ip) == 1 && length(digitalocean_ssh_key.app-key) == 0
+    error_message = "Expected a reserved IP and no SSH key"
+  }
+
+  assert {
+    condition     = length(digitalocean_firewall.app-firewall.inbound_rule) == 3
+    error_message = "Expected the ICMP, HTTP and HTTPS default rules"
+  }
+}
+
+run "app_rules" {
+  command = plan
+
+  variables {
+    reserved_ip    = false
+    ssh_public_key = "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIEXAMPLEKEYEXAMPLEKEYEXAMPLEKEYEXAMPLE demo"
+    firewall_rules = [
+      { protocol = "udp", port = "51820", source_ips = ["203.0.113.7"] },
+      { protocol = "tcp", port = "8000-8100" },
+    ]
+  }
+
+  assert {
+    condition     = length(digitalocean_reserved_ip.app-ip) == 0 && length(digitalocean_ssh_key.app-key) == 1
+    error_message = "Expected an SSH key and no reserved IP"
+  }
+
+  assert {
+    condition     = length(digitalocean_firewall.app-firewall.inbound_rule) == 2
+    error_message = "Firewall rules were not passed to the firewall"
+  }
+}
+
+run "unsupported_protocol" {
+  command = plan
+
+  variables {
+    firewall_rules = [{ protocol = "gre" }]
+  }
+
+  expect_failures = [digitalocean_firewall.app-firewall]
+}
@@ -0,0 +1,56 @@
+variable "do_token" {
+  type      = string
+  sensitive = true
+}
+
+variable "node-name" {
+  type = string
+}
+
+variable "size" {
+  type = string
+}
+
+variable "image" {
+  type    = string
+  default = "debian-12-x64"
+}
+
+variable "region" {
+  type = string
+}
+
+variable "volume_size" {
+  type = number
+}
+
+variable "reserved_ip" {
+  type    = bool
+  default = true
+}
+
+variable "user_data" {
+  type      = string
+  default   = ""
+  sensitive = true
+}
+
+variable "ssh_public_key" {
+  type    = string
+  default = ""
+}
+
+variable "firewall_rules" {
+  type = list(object({
+    direction   = optional(string, "in")
+    protocol    = string
+    port        = optional(string)
+    source_ips  = optional(list(string), ["0.0.0.0/0", "::/0"])
+    description = optional(string)
+  }))
+  default = [
+    { protocol = "icmp" },
+    { protocol = "tcp", port = "80" },
+    { protocol = "tcp", port = "443" },
+  ]
+}
@@ -1,34 +1,22 @@
 resource "hcloud_firewall" "app-firewall" {
-  name = "app-firewall"
-  rule {
-    direction = "in"
-    protocol  = "icmp"
-    source_ips = [
-      "0.0.0.0/0",
-      "::/0"
-    ]
-  }
-
-  rule {
-    direction = "in"
-    protocol  = "tcp"
-    port      = "80"
-    source_ips = [
-      "0.0.0.0/0",
-      "::/0"
-    ]
-  }
+  name = "${var.node-name}-firewall"
 
-  rule {
-    direction = "in"
-    protocol  = "tcp"
-    port      = "443"
-    source_ips = [
-      "0.0.0.0/0",
-      "::/0"
-    ]
+  dynamic "rule" {
+    for_each = var.firewall_rules
+    content {
+      direction   = rule.value.direction
+      protocol    = rule.value.protocol
+      port        = rule.value.port
+      source_ips  = rule.value.source_ips
+      description = rule.value.description
+    }
   }
+}
 
+resource "hcloud_ssh_key" "app-key" {
+  count      = var.ssh_public_key == "" ? 0 : 1
+  name       = "${var.node-name}-key"
+  public_key = var.ssh_public_key
 }
 
 resource "hcloud_server" "app-server" {
@@ -36,9 +24,10 @@ resource "hcloud_server" "app-server" {
   server_type  = var.size
   image        = var.distro
   location     = var.location
-  user_data    = ""
+  user_data    = var.user_data
+  ssh_keys     = hcloud_ssh_key.app-key[*].id
   firewall_ids = [hcloud_firewall.app-firewall.id]
   depends_on = [
-    hcloud_firewall.myfirewall
+    hcloud_firewall.app-firewall
   ]
 }
\ No newline at end of file
@@ -0,0 +1,11 @@
+output "ipv4_address" {
+  value = hcloud_server.app-server.ipv4_address
+}
+
+output "ipv6_address" {
+  value = hcloud_server.app-server.ipv6_address
+}
+
+output "server_id" {
+  value = hcloud_server.app-server.id
+}
@@ -1,4 +1,7 @@
 terraform {
+  # The generated S3 backend locks state with use_lockfile, which older releases reject
+  required_version = ">= 1.10"
+
   required_providers {
     hcloud = {
       source  = "hetznercloud/hcloud"
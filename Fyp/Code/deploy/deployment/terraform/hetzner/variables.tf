@@ -1,26 +1,47 @@
 variable "hcloud_token" {
-  type    = string
-  default = "__HCLOUD_TOKEN__"
+  type      = string
   sensitive = true
 }
 
 variable "node-name" {
   type = string
-  default = "__NODE_NAME__"
 }
 
 variable "size" {
   type = string
-  default = "__SIZE__"
 }
 
 variable "distro" {
-  type = string
-  default = "__DISTRO__"
+  type    = string
+  default = "debian-12"
 }
 
 variable "location" {
   type = string
-  default = "__LOCATION__"
 }
 
+variable "user_data" {
+  type      = string
+  default   = ""
+  sensitive = true
+}
+
+variable "ssh_public_key" {
+  type    = string
+  default = ""
+}
+
+variable "firewall_rules" {
+  type = list(object({
+    direction   = optional(string, "in")
+    protocol    = string
+    port        = optional(string)
+    source_ips  = optional(list(string), ["0.0.0.0/0", "::/0"])
+    description = optional(string)
+  }))
+  default = [
+    { protocol = "icmp" },
+    { protocol = "tcp", port = "80" },
+    { protocol = "tcp", port = "443" },
+  ]
+}
@@ -17,9 +17,9 @@ const ServerSelection: React.FC<ServerSelectionProps> = ({
         value={selectedServer}
         onChange={(e) => handleInputChange("selectedServer", e.target.value)}
       >
+        <FormControlLabel value="hetzner" control={<Radio />} label="Hetzner" />
         <FormControlLabel value="aws" control={<Radio />} label="AWS" />
-        <FormControlLabel value="azure" control={<Radio />} label="Azure" />
-        <FormControlLabel value="gcp" control={<Radio />} label="GCP" />
+        <FormControlLabel value="digitalocean" control={<Radio />} label="DigitalOcean" />
       </RadioGroup>
     </div>
   );
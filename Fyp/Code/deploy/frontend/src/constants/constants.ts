@@ -39,11 +39,11 @@ export const INSTANCE_TYPES = [
   },
 ];
 
-// Rename `SERVICES` to `CLOUD_PROVIDERS` (AWS, Azure, GCP)
+// Rename `SERVICES` to `CLOUD_PROVIDERS`; keep in step with the backend's providers
 export const CLOUD_PROVIDERS = [
+  { id: "Hetzner", name: "Hetzner Cloud" },
   { id: "AWS", name: "Amazon Web Services" },
-  { id: "Azure", name: "Microsoft Azure" },
-  { id: "GCP", name: "Google Cloud Platform" },
+  { id: "DigitalOcean", name: "DigitalOcean" },
 ];
 
 export const APPLICATION_TYPES = [
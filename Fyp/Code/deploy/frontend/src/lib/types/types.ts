@@ -1,5 +1,5 @@
 // src/types/types.ts
-export type CloudProviderType = "AWS" | "Azure" | "GCP";
+export type CloudProviderType = "Hetzner" | "AWS" | "DigitalOcean";
 export type InstanceType = "small" | "medium" | "large";
 
 export type ApplicationType =